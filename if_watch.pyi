@@ -0,0 +1,30 @@
+import ipaddress
+from typing import AsyncIterator, Iterator, List, Literal, Optional, Union
+
+class Address:
+    @property
+    def net(self) -> str: ...
+    @property
+    def ip(self) -> Union[ipaddress.IPv4Address, ipaddress.IPv6Address]: ...
+    @property
+    def prefix_len(self) -> int: ...
+    @property
+    def if_index(self) -> Optional[int]: ...
+    @property
+    def if_name(self) -> str: ...
+
+class AddressEvent:
+    @property
+    def kind(self) -> Literal["up", "down", "changed"]: ...
+    @property
+    def address(self) -> Address: ...
+
+class IfWatcher(Iterator[AddressEvent], AsyncIterator[AddressEvent]):
+    def __init__(self) -> None: ...
+    def addresses(self) -> List[Address]: ...
+    def __iter__(self) -> IfWatcher: ...
+    def __next__(self) -> AddressEvent: ...
+    def __aiter__(self) -> IfWatcher: ...
+    async def __anext__(self) -> AddressEvent: ...
+
+def current_addresses() -> List[Address]: ...
@@ -0,0 +1,151 @@
+#ifndef IF_WATCH_H
+#define IF_WATCH_H
+
+/* Generated with cbindgen:0.26.0 */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Size of the buffer holding the nul terminated interface name.
+ */
+#define IF_WATCH_NAME_LEN 256
+
+/**
+ * Kind of an address event.
+ */
+typedef enum IfWatchEventKind {
+  /**
+   * A new local address has been added.
+   */
+  IfWatchUp = 0,
+  /**
+   * A local address has been deleted.
+   */
+  IfWatchDown = 1,
+  /**
+   * The flags of a local address have changed. Only reported on Linux.
+   */
+  IfWatchChanged = 2,
+} IfWatchEventKind;
+
+/**
+ * Opaque watcher handle.
+ */
+typedef struct IfWatchHandle IfWatchHandle;
+
+/**
+ * A local address together with the interface it is assigned to.
+ */
+typedef struct IfWatchAddress {
+  /**
+   * Address family, `4` or `6`.
+   */
+  uint8_t family;
+  /**
+   * Address in network byte order. IPv4 addresses use the first 4 bytes.
+   */
+  uint8_t addr[16];
+  /**
+   * Prefix length of the network.
+   */
+  uint8_t prefix_len;
+  /**
+   * Index of the interface, `0` if unknown.
+   */
+  uint32_t if_index;
+  /**
+   * Nul terminated name of the interface, truncated if necessary.
+   */
+  uint8_t if_name[IF_WATCH_NAME_LEN];
+} IfWatchAddress;
+
+/**
+ * An address change event.
+ */
+typedef struct IfWatchEvent {
+  /**
+   * Whether the address has been added, deleted or changed.
+   */
+  enum IfWatchEventKind kind;
+  /**
+   * The address.
+   */
+  struct IfWatchAddress address;
+} IfWatchEvent;
+
+/**
+ * Callback invoked on the watcher thread for every event.
+ */
+typedef void (*IfWatchCallback)(const struct IfWatchEvent *event, void *user);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a watcher and waits until the current addresses have been loaded.
+ *
+ * Returns `NULL` if the watcher could not be created. The watcher must be
+ * released with `if_watch_free`.
+ */
+struct IfWatchHandle *if_watch_new(void);
+
+/**
+ * Waits for the next event and writes it to `event`.
+ *
+ * A negative `timeout_ms` waits indefinitely. Returns `1` if an event has
+ * been written, `0` on timeout and `-1` if the watcher failed.
+ *
+ * # Safety
+ *
+ * `watcher` must have been returned by `if_watch_new` and `event` must point
+ * to writable memory for an `IfWatchEvent`.
+ */
+int if_watch_next(struct IfWatchHandle *watcher, struct IfWatchEvent *event, int timeout_ms);
+
+/**
+ * Registers a callback invoked on the watcher thread for every event.
+ *
+ * Events are no longer queued for `if_watch_next` while a callback is
+ * registered. Passing `NULL` as `callback` removes the callback.
+ *
+ * # Safety
+ *
+ * `watcher` must have been returned by `if_watch_new`. `callback` must be
+ * safe to call from another thread with `user` until it is replaced or the
+ * watcher is freed.
+ */
+void if_watch_set_callback(struct IfWatchHandle *watcher, IfWatchCallback callback, void *user);
+
+/**
+ * Copies up to `len` current addresses to `addrs`.
+ *
+ * Returns the total number of current addresses, which may be larger than
+ * `len`. `addrs` may be `NULL` if `len` is `0`.
+ *
+ * # Safety
+ *
+ * `watcher` must have been returned by `if_watch_new` and `addrs` must point
+ * to writable memory for `len` `IfWatchAddress` values.
+ */
+size_t if_watch_addresses(struct IfWatchHandle *watcher, struct IfWatchAddress *addrs, size_t len);
+
+/**
+ * Stops the watcher and releases its resources.
+ *
+ * # Safety
+ *
+ * `watcher` must have been returned by `if_watch_new` or be `NULL`, and must
+ * not be used afterwards. Must not be called from the callback.
+ */
+void if_watch_free(struct IfWatchHandle *watcher);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* IF_WATCH_H */
@@ -1,12 +1,8 @@
-use crate::{IfEvent, IpNet, Ipv4Net, Ipv6Net};
 use core_foundation::array::CFArray;
 use core_foundation::runloop::{kCFRunLoopCommonModes, CFRunLoop};
 use core_foundation::string::CFString;
-use fnv::FnvHashSet;
 use futures::channel::mpsc;
-use futures::stream::{FusedStream, Stream};
-use if_addrs::IfAddr;
-use std::collections::VecDeque;
+use futures::stream::Stream;
 use std::io::Result;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -14,14 +10,22 @@ use system_configuration::dynamic_store::{
     SCDynamicStore, SCDynamicStoreBuilder, SCDynamicStoreCallBackContext,
 };
 
+/// Name of the backend in `tracing` events and metrics.
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+pub(crate) const BACKEND: &str = "apple";
+
 #[cfg(feature = "tokio")]
 pub mod tokio {
     //! An interface watcher.
     //! **On Apple Platforms there is no difference between `tokio` and `smol` features,**
     //! **this was done to maintain the api compatible with other platforms**.
 
+    pub use crate::connectivity::ConnectivityWatcher;
+    pub use crate::ifaddrs::current_addresses;
+    pub use crate::primary::PrimaryWatcher;
+
     /// Watches for interface changes.
-    pub type IfWatcher = super::IfWatcher;
+    pub type IfWatcher = crate::ifaddrs::IfWatcher;
 }
 
 #[cfg(feature = "smol")]
@@ -30,103 +34,36 @@ pub mod smol {
     //! **On Apple platforms there is no difference between `tokio` and `smol` features,**
     //! **this was done to maintain the api compatible with other platforms**.
 
+    pub use crate::connectivity::ConnectivityWatcher;
+    pub use crate::ifaddrs::current_addresses;
+    pub use crate::primary::PrimaryWatcher;
+
     /// Watches for interface changes.
-    pub type IfWatcher = super::IfWatcher;
+    pub type IfWatcher = crate::ifaddrs::IfWatcher;
 }
 
+/// Reports changes of the dynamic store, which are received on a background
+/// thread.
 #[derive(Debug)]
-pub struct IfWatcher {
-    addrs: FnvHashSet<IpNet>,
-    queue: VecDeque<IfEvent>,
+pub(crate) struct Notifier {
     rx: mpsc::Receiver<()>,
 }
 
-impl IfWatcher {
-    pub fn new() -> Result<Self> {
+impl Notifier {
+    pub(crate) fn new() -> Result<Self> {
         let (tx, rx) = mpsc::channel(1);
         std::thread::spawn(|| background_task(tx));
-        let mut watcher = Self {
-            addrs: Default::default(),
-            queue: Default::default(),
-            rx,
-        };
-        watcher.resync()?;
-        Ok(watcher)
-    }
-
-    fn resync(&mut self) -> Result<()> {
-        let addrs = if_addrs::get_if_addrs()?;
-        for old_addr in self.addrs.clone() {
-            if addrs
-                .iter()
-                .find(|addr| addr.ip() == old_addr.addr())
-                .is_none()
-            {
-                self.addrs.remove(&old_addr);
-                self.queue.push_back(IfEvent::Down(old_addr));
-            }
-        }
-        for new_addr in addrs {
-            let ipnet = ifaddr_to_ipnet(new_addr.addr);
-            if self.addrs.insert(ipnet) {
-                self.queue.push_back(IfEvent::Up(ipnet));
-            }
-        }
-        Ok(())
-    }
-
-    /// Iterate over current networks.
-    pub fn iter(&self) -> impl Iterator<Item = &IpNet> {
-        self.addrs.iter()
-    }
-
-    /// Poll for an address change event.
-    pub fn poll_if_event(&mut self, cx: &mut Context) -> Poll<Result<IfEvent>> {
-        loop {
-            if let Some(event) = self.queue.pop_front() {
-                return Poll::Ready(Ok(event));
-            }
-            if Pin::new(&mut self.rx).poll_next(cx).is_pending() {
-                return Poll::Pending;
-            }
-            if let Err(error) = self.resync() {
-                return Poll::Ready(Err(error));
-            }
-        }
-    }
-}
-
-impl Stream for IfWatcher {
-    type Item = Result<IfEvent>;
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::into_inner(self).poll_if_event(cx).map(Some)
+        Ok(Self { rx })
     }
-}
-
-impl FusedStream for IfWatcher {
-    fn is_terminated(&self) -> bool {
-        false
-    }
-}
 
-fn ifaddr_to_ipnet(addr: IfAddr) -> IpNet {
-    match addr {
-        IfAddr::V4(ip) => {
-            let prefix_len = (!u32::from_be_bytes(ip.netmask.octets())).leading_zeros();
-            IpNet::V4(
-                Ipv4Net::new(ip.ip, prefix_len as u8).expect("if_addrs returned a valid prefix"),
-            )
-        }
-        IfAddr::V6(ip) => {
-            let prefix_len = (!u128::from_be_bytes(ip.netmask.octets())).leading_zeros();
-            IpNet::V6(
-                Ipv6Net::new(ip.ip, prefix_len as u8).expect("if_addrs returned a valid prefix"),
-            )
-        }
+    pub(crate) fn poll_change(&mut self, cx: &mut Context) -> Poll<()> {
+        Pin::new(&mut self.rx).poll_next(cx).map(|_| ())
     }
 }
 
 fn callback(_store: SCDynamicStore, _changed_keys: CFArray<CFString>, info: &mut mpsc::Sender<()>) {
+    #[cfg(feature = "tracing")]
+    crate::trace::notification(BACKEND, "dynamic_store", None);
     match info.try_send(()) {
         Err(err) if err.is_disconnected() => CFRunLoop::get_current().stop(),
         _ => {}
@@ -0,0 +1,321 @@
+//! Runs a command for address events, similar to NetworkManager-dispatcher.
+use if_watch::{IfAddressEvent, IpNet};
+use std::collections::{HashMap, VecDeque};
+use std::process::{Child, Command};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Interval at which running commands are checked for completion.
+const WAIT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Dispatcher settings.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Program and arguments to run for every event.
+    pub command: Vec<String>,
+    /// Time without further events before pending events are dispatched.
+    pub debounce: Duration,
+    /// Maximum number of commands running at the same time.
+    pub jobs: usize,
+    /// Time after which a running command is killed.
+    pub timeout: Option<Duration>,
+}
+
+/// Runs the configured command on a background thread for every event.
+///
+/// Events are collected until no further event arrives for the debounce
+/// duration. An address that goes up and down again within that window does
+/// not cause the command to run at all.
+pub struct Dispatcher {
+    tx: Sender<IfAddressEvent>,
+    thread: JoinHandle<()>,
+}
+
+impl Dispatcher {
+    /// Starts the dispatcher thread.
+    pub fn new(config: Config) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let thread = thread::spawn(move || run(config, rx));
+        Self { tx, thread }
+    }
+
+    /// Schedules the command to run for `event`.
+    pub fn dispatch(&self, event: IfAddressEvent) {
+        // The receiver only goes away when the dispatcher thread panicked.
+        let _ = self.tx.send(event);
+    }
+
+    /// Runs the command for all pending events and waits for it to finish.
+    pub fn finish(self) {
+        drop(self.tx);
+        let _ = self.thread.join();
+    }
+}
+
+fn run(config: Config, rx: Receiver<IfAddressEvent>) {
+    let jobs = Jobs::new(config.jobs);
+    let mut pending = Vec::new();
+    let mut deadline = Instant::now();
+    loop {
+        let event = if pending.is_empty() {
+            rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        };
+        match event {
+            Ok(event) => {
+                coalesce(&mut pending, event);
+                deadline = Instant::now() + config.debounce;
+            }
+            Err(err) => {
+                for event in pending.drain(..) {
+                    jobs.spawn(&config, event);
+                }
+                if err == RecvTimeoutError::Disconnected {
+                    break;
+                }
+            }
+        }
+    }
+    jobs.wait();
+}
+
+/// Adds `event` to the pending events, cancelling out a pending event of the
+/// opposite kind for the same address.
+///
+/// Addresses are matched by network and interface only, since their flags
+/// change while they are assigned, e.g. once they are no longer tentative.
+fn coalesce(pending: &mut Vec<IfAddressEvent>, event: IfAddressEvent) {
+    let opposite = pending.iter().position(|other| {
+        key(other) == key(&event)
+            && matches!(
+                (other, &event),
+                (IfAddressEvent::Up(_), IfAddressEvent::Down(_))
+                    | (IfAddressEvent::Down(_), IfAddressEvent::Up(_))
+            )
+    });
+    match opposite {
+        Some(index) => {
+            pending.remove(index);
+        }
+        None => pending.push(event),
+    }
+}
+
+/// Limits the number of concurrently running commands.
+///
+/// Commands for the same address run one after another in the order of their
+/// events, so that a hook never sees an address go down before the command for
+/// it coming up has finished. Only commands for different addresses run in
+/// parallel.
+#[derive(Clone)]
+struct Jobs {
+    limit: usize,
+    state: Arc<(Mutex<JobState>, Condvar)>,
+}
+
+#[derive(Default)]
+struct JobState {
+    running: usize,
+    /// Events waiting for the running command of their address, keyed by the
+    /// network and interface index like in `coalesce`.
+    queued: HashMap<(IpNet, Option<u32>), VecDeque<IfAddressEvent>>,
+}
+
+impl Jobs {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            state: Default::default(),
+        }
+    }
+
+    /// Runs the command for `event` once the command for an earlier event of
+    /// the same address has finished and fewer than `limit` commands are
+    /// running.
+    fn spawn(&self, config: &Config, event: IfAddressEvent) {
+        let key = key(&event);
+        let (lock, cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        if let Some(queued) = state.queued.get_mut(&key) {
+            queued.push_back(event);
+            return;
+        }
+        while state.running >= self.limit {
+            state = cvar.wait(state).unwrap();
+        }
+        state.running += 1;
+        state.queued.insert(key, VecDeque::new());
+        drop(state);
+
+        let jobs = self.clone();
+        let config = config.clone();
+        thread::spawn(move || {
+            let mut event = event;
+            loop {
+                if let Err(err) = execute(&config, &event) {
+                    let addr = event.address();
+                    eprintln!(
+                        "if-watch: command failed for {} dev {}: {}",
+                        addr.net, addr.if_name, err
+                    );
+                }
+                let (lock, cvar) = &*jobs.state;
+                let mut state = lock.lock().unwrap();
+                let queued = state.queued.get_mut(&key).unwrap();
+                match queued.pop_front() {
+                    Some(next) => event = next,
+                    None => {
+                        state.queued.remove(&key);
+                        state.running -= 1;
+                        cvar.notify_all();
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Waits for all running commands to finish.
+    fn wait(&self) {
+        let (lock, cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        while state.running > 0 {
+            state = cvar.wait(state).unwrap();
+        }
+    }
+}
+
+fn key(event: &IfAddressEvent) -> (IpNet, Option<u32>) {
+    let addr = event.address();
+    (addr.net, addr.if_index)
+}
+
+fn execute(config: &Config, event: &IfAddressEvent) -> Result<(), String> {
+    let (kind, addr) = match event {
+        IfAddressEvent::Up(addr) => ("up", addr),
+        IfAddressEvent::Down(addr) => ("down", addr),
+        IfAddressEvent::Changed { new, .. } => ("changed", new),
+    };
+    let (program, args) = config.command.split_first().ok_or("no command")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .env("IF_WATCH_EVENT", kind)
+        .env("IF_WATCH_ADDR", addr.net.addr().to_string())
+        .env("IF_WATCH_PREFIX", addr.net.prefix_len().to_string())
+        .env("IF_WATCH_IFNAME", &addr.if_name)
+        .spawn()
+        .map_err(|err| err.to_string())?;
+    let status = match config.timeout {
+        Some(timeout) => wait_timeout(&mut child, timeout)?,
+        None => child.wait().map_err(|err| err.to_string())?,
+    };
+    if !status.success() {
+        return Err(format!("exited with {}", status));
+    }
+    Ok(())
+}
+
+fn wait_timeout(child: &mut Child, timeout: Duration) -> Result<std::process::ExitStatus, String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait().map_err(|err| err.to_string())? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("timed out after {:?}", timeout));
+        }
+        thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::coalesce;
+    use if_watch::{IfAddress, IfAddressEvent, IfAddressFlags};
+
+    fn addr(net: &str) -> IfAddress {
+        IfAddress {
+            net: net.parse().unwrap(),
+            if_index: Some(2),
+            if_name: "eth0".into(),
+            netns: None,
+            flags: None,
+        }
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let mut pending = Vec::new();
+        coalesce(&mut pending, IfAddressEvent::Up(addr("10.0.0.1/24")));
+        coalesce(&mut pending, IfAddressEvent::Up(addr("10.0.0.2/24")));
+        coalesce(&mut pending, IfAddressEvent::Down(addr("10.0.0.1/24")));
+        assert_eq!(pending, vec![IfAddressEvent::Up(addr("10.0.0.2/24"))]);
+
+        coalesce(&mut pending, IfAddressEvent::Down(addr("10.0.0.2/24")));
+        coalesce(&mut pending, IfAddressEvent::Down(addr("10.0.0.3/24")));
+        coalesce(&mut pending, IfAddressEvent::Up(addr("10.0.0.3/24")));
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_coalesce_flags() {
+        // The address is reported as tentative when it comes up and goes down
+        // once duplicate address detection has completed.
+        let mut tentative = addr("2001:db8::1/64");
+        tentative.flags = Some(IfAddressFlags {
+            tentative: true,
+            ..Default::default()
+        });
+        let mut assigned = addr("2001:db8::1/64");
+        assigned.flags = Some(IfAddressFlags::default());
+        let mut pending = Vec::new();
+        coalesce(&mut pending, IfAddressEvent::Up(tentative));
+        coalesce(&mut pending, IfAddressEvent::Down(assigned));
+        assert!(pending.is_empty());
+
+        // The same address on another interface is a different address.
+        let mut other = addr("2001:db8::1/64");
+        other.if_index = Some(3);
+        coalesce(&mut pending, IfAddressEvent::Up(addr("2001:db8::1/64")));
+        coalesce(&mut pending, IfAddressEvent::Down(other));
+        assert_eq!(pending.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_jobs_order() {
+        use super::{Config, Jobs};
+        use std::time::Duration;
+
+        let log = std::env::temp_dir().join(format!("if-watch-jobs-{}", std::process::id()));
+        // The command for an address coming up takes longer than the one for
+        // it going down.
+        let script = format!(
+            "[ $IF_WATCH_EVENT = up ] && sleep 0.2; echo $IF_WATCH_EVENT $IF_WATCH_ADDR >> {}",
+            log.display()
+        );
+        let config = Config {
+            command: vec!["sh".into(), "-c".into(), script],
+            debounce: Duration::ZERO,
+            jobs: 4,
+            timeout: None,
+        };
+        let jobs = Jobs::new(config.jobs);
+        jobs.spawn(&config, IfAddressEvent::Up(addr("10.0.0.1/24")));
+        jobs.spawn(&config, IfAddressEvent::Up(addr("10.0.0.2/24")));
+        jobs.spawn(&config, IfAddressEvent::Down(addr("10.0.0.1/24")));
+        jobs.wait();
+        let output = std::fs::read_to_string(&log).unwrap();
+        std::fs::remove_file(&log).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        let up = lines.iter().position(|line| *line == "up 10.0.0.1");
+        let down = lines.iter().position(|line| *line == "down 10.0.0.1");
+        assert!(up < down, "{:?}", lines);
+        assert_eq!(lines.len(), 3);
+    }
+}
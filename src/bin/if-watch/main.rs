@@ -0,0 +1,147 @@
+//! Prints the local addresses and follows their changes.
+use clap::Parser;
+use dispatch::{Config, Dispatcher};
+use futures::executor::block_on;
+use futures::future::poll_fn;
+use if_watch::smol::{current_addresses, IfWatcher};
+use if_watch::{IfAddress, IfAddressEvent};
+use std::io::{ErrorKind, Result, Write};
+use std::process::ExitCode;
+use std::time::Duration;
+
+mod dispatch;
+
+const EXIT_STATUS: &str = "\
+Exit status:
+  0  addresses have been printed
+  1  --once was given and no address matched the filters
+  2  invalid arguments or the watcher failed";
+
+/// Cross platform network address monitor.
+///
+/// Prints the current local addresses followed by addresses being added,
+/// removed or changing their flags, similar to `ip monitor address`.
+///
+/// When a command is given it is run for every address being added or
+/// removed with the environment variables `IF_WATCH_EVENT` (`up` or `down`),
+/// `IF_WATCH_ADDR`, `IF_WATCH_PREFIX` and `IF_WATCH_IFNAME` set.
+#[derive(Debug, Parser)]
+#[command(version, after_help = EXIT_STATUS)]
+struct Args {
+    /// Print one JSON object per line instead of text.
+    #[arg(long)]
+    json: bool,
+    /// Only report IPv4 addresses.
+    #[arg(short = '4', long)]
+    ipv4: bool,
+    /// Only report IPv6 addresses.
+    #[arg(short = '6', long)]
+    ipv6: bool,
+    /// Only report addresses of this interface. May be given multiple times.
+    #[arg(short, long = "interface", value_name = "NAME")]
+    interfaces: Vec<String>,
+    /// Print the current addresses and exit.
+    #[arg(long)]
+    once: bool,
+    /// Milliseconds without further events before the command is run.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    debounce: u64,
+    /// Maximum number of commands running at the same time. Commands for the
+    /// same address always run one after another.
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    jobs: usize,
+    /// Seconds after which a running command is killed.
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
+    /// Command to run for every event.
+    #[arg(last = true, value_name = "COMMAND")]
+    command: Vec<String>,
+}
+
+impl Args {
+    fn matches(&self, addr: &IfAddress) -> bool {
+        let family = match addr.net.addr().is_ipv4() {
+            true => self.ipv4 || !self.ipv6,
+            false => self.ipv6 || !self.ipv4,
+        };
+        let interface = self.interfaces.is_empty() || self.interfaces.contains(&addr.if_name);
+        family && interface
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let dispatcher = (!args.command.is_empty()).then(|| {
+        Dispatcher::new(Config {
+            command: args.command.clone(),
+            debounce: Duration::from_millis(args.debounce),
+            jobs: args.jobs,
+            timeout: args.timeout.map(Duration::from_secs),
+        })
+    });
+    let result = block_on(run(&args, dispatcher.as_ref()));
+    if let Some(dispatcher) = dispatcher {
+        dispatcher.finish();
+    }
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("if-watch: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Runs the monitor. Returns whether an address has been printed.
+async fn run(args: &Args, dispatcher: Option<&Dispatcher>) -> Result<bool> {
+    if args.once {
+        let mut addrs = current_addresses().await?;
+        addrs.retain(|addr| args.matches(addr));
+        addrs.sort_by_key(|addr| (addr.if_index, addr.net));
+        for addr in &addrs {
+            print_address(args, addr)?;
+            if let Some(dispatcher) = dispatcher {
+                dispatcher.dispatch(IfAddressEvent::Up(addr.clone()));
+            }
+        }
+        return Ok(!addrs.is_empty());
+    }
+    let mut watcher = IfWatcher::new()?;
+    loop {
+        let event = poll_fn(|cx| watcher.poll_address_event(cx)).await?;
+        if args.matches(event.address()) {
+            print_event(args, &event)?;
+            // The command only runs for addresses going up or down.
+            let dispatcher = dispatcher.filter(|_| event.to_if_event().is_some());
+            if let Some(dispatcher) = dispatcher {
+                dispatcher.dispatch(event);
+            }
+        }
+    }
+}
+
+fn print_address(args: &Args, addr: &IfAddress) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    if args.json {
+        serde_json::to_writer(&mut stdout, addr)?;
+        writeln!(stdout)
+    } else {
+        writeln!(stdout, "{} dev {}", addr.net, addr.if_name)
+    }
+}
+
+fn print_event(args: &Args, event: &IfAddressEvent) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    if args.json {
+        serde_json::to_writer(&mut stdout, event)?;
+        return writeln!(stdout);
+    }
+    let (kind, addr) = match event {
+        IfAddressEvent::Up(addr) => ("up", addr),
+        IfAddressEvent::Down(addr) => ("down", addr),
+        IfAddressEvent::Changed { new, .. } => ("changed", new),
+    };
+    writeln!(stdout, "{} {} dev {}", kind, addr.net, addr.if_name)
+}
@@ -0,0 +1,187 @@
+//! A watcher running on a background thread for callers without an async
+//! runtime, used by the C and Python bindings.
+use crate::smol::IfWatcher;
+use crate::{IfAddress, IfAddressEvent};
+use futures::channel::oneshot;
+use futures::executor::block_on;
+use futures::future::{self, Either};
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Callback invoked on the watcher thread for every event.
+pub(crate) type Callback = Arc<dyn Fn(&IfAddressEvent) + Send + Sync>;
+
+/// Function called once when an event has been queued or the watcher failed.
+pub(crate) type Waker = Box<dyn FnOnce() + Send>;
+
+pub(crate) struct BlockingWatcher {
+    shared: Arc<(Mutex<Shared>, Condvar)>,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    addrs: Vec<IfAddress>,
+    queue: VecDeque<IfAddressEvent>,
+    callback: Option<Callback>,
+    wakers: Vec<Waker>,
+    synced: bool,
+    error: Option<(ErrorKind, String)>,
+}
+
+impl Shared {
+    fn error(&self) -> Option<Error> {
+        let (kind, msg) = self.error.as_ref()?;
+        Some(Error::new(*kind, msg.clone()))
+    }
+}
+
+impl BlockingWatcher {
+    /// Starts the watcher thread and waits until the current addresses have
+    /// been loaded.
+    pub fn new() -> Result<Self> {
+        let shared = Arc::new((Mutex::new(Shared::default()), Condvar::new()));
+        let (shutdown, stopped) = oneshot::channel();
+        let thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                let result = block_on(run(&shared, stopped));
+                let (lock, cvar) = &*shared;
+                let mut state = lock.lock().unwrap();
+                state.error = Some(match result {
+                    Ok(()) => (ErrorKind::Other, "watcher stopped".into()),
+                    Err(err) => (err.kind(), err.to_string()),
+                });
+                cvar.notify_all();
+                let wakers = std::mem::take(&mut state.wakers);
+                drop(state);
+                wakers.into_iter().for_each(|waker| waker());
+            })
+        };
+        let watcher = Self {
+            shared,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        };
+        {
+            let (lock, cvar) = &*watcher.shared;
+            let state = cvar
+                .wait_while(lock.lock().unwrap(), |state| {
+                    !state.synced && state.error.is_none()
+                })
+                .unwrap();
+            if let Some(err) = state.error() {
+                return Err(err);
+            }
+        }
+        Ok(watcher)
+    }
+
+    /// Waits for the next queued event. Returns `None` if `timeout` elapsed
+    /// and an error if the watcher failed.
+    pub fn next(&self, timeout: Option<Duration>) -> Result<Option<IfAddressEvent>> {
+        let (lock, cvar) = &*self.shared;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = lock.lock().unwrap();
+        loop {
+            if let Some(event) = state.queue.pop_front() {
+                return Ok(Some(event));
+            }
+            if let Some(err) = state.error() {
+                return Err(err);
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    if timeout.is_zero() {
+                        return Ok(None);
+                    }
+                    cvar.wait_timeout(state, timeout).unwrap().0
+                }
+                None => cvar.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Current addresses.
+    pub fn addresses(&self) -> Vec<IfAddress> {
+        let (lock, _) = &*self.shared;
+        lock.lock().unwrap().addrs.clone()
+    }
+
+    /// Sets the callback invoked instead of queueing events.
+    #[cfg(feature = "ffi")]
+    pub fn set_callback(&self, callback: Option<Callback>) {
+        let (lock, _) = &*self.shared;
+        lock.lock().unwrap().callback = callback;
+    }
+
+    /// Calls `waker` on the watcher thread once an event has been queued or the
+    /// watcher failed, or right away if that is already the case.
+    #[cfg(feature = "python")]
+    pub fn wake(&self, waker: Waker) {
+        let (lock, _) = &*self.shared;
+        let mut state = lock.lock().unwrap();
+        if state.queue.is_empty() && state.error.is_none() {
+            state.wakers.push(waker);
+        } else {
+            drop(state);
+            waker();
+        }
+    }
+
+    /// Stops the watcher thread and waits for it to exit.
+    pub fn stop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for BlockingWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+async fn run(shared: &(Mutex<Shared>, Condvar), mut stopped: oneshot::Receiver<()>) -> Result<()> {
+    let (lock, cvar) = shared;
+    let mut watcher = IfWatcher::new()?;
+    future::poll_fn(|cx| watcher.poll_synced(cx)).await?;
+    {
+        let mut state = lock.lock().unwrap();
+        state.addrs = watcher.addresses().cloned().collect();
+        state.synced = true;
+        cvar.notify_all();
+    }
+    loop {
+        let next = future::poll_fn(|cx| watcher.poll_address_event(cx));
+        let event = match future::select(next, &mut stopped).await {
+            Either::Left((event, _)) => event?,
+            Either::Right(_) => return Ok(()),
+        };
+        let mut state = lock.lock().unwrap();
+        state.addrs = watcher.addresses().cloned().collect();
+        match state.callback.clone() {
+            Some(callback) => {
+                drop(state);
+                callback(&event);
+            }
+            None => {
+                state.queue.push_back(event);
+                cvar.notify_all();
+                // Wakers may block on other locks, such as the GIL.
+                let wakers = std::mem::take(&mut state.wakers);
+                drop(state);
+                wakers.into_iter().for_each(|waker| waker());
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Classification of the connectivity on platforms without route watching.
+use crate::ifaddrs::IfWatcher;
+use crate::primary::probe_source;
+use crate::{Connectivity, ConnectivityEvent, IpFamily};
+use futures::ready;
+use futures::stream::{FusedStream, Stream};
+use std::collections::VecDeque;
+use std::io::Result;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Watches the connectivity per address family.
+///
+/// The connectivity is classified from the current addresses and from
+/// whether the platform has a route to an address outside of the local
+/// networks, which is checked by connecting a UDP socket whenever an address
+/// changes. The state of the interfaces and route changes that do not
+/// coincide with an address change are not noticed on this platform. An event
+/// is emitted for each family whose connectivity changed, starting with the
+/// initial connectivity unless it is [`Offline`](Connectivity::Offline).
+#[derive(Debug)]
+pub struct ConnectivityWatcher {
+    watcher: IfWatcher,
+    synced: bool,
+    connectivity: [Connectivity; 2],
+    queue: VecDeque<ConnectivityEvent>,
+}
+
+impl ConnectivityWatcher {
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            watcher: IfWatcher::new()?,
+            synced: false,
+            connectivity: Default::default(),
+            queue: Default::default(),
+        })
+    }
+
+    /// The current connectivity of `family`.
+    pub fn connectivity(&self, family: IpFamily) -> Connectivity {
+        self.connectivity[family as usize]
+    }
+
+    fn update(&mut self) {
+        for family in [IpFamily::V4, IpFamily::V6] {
+            let default_route = probe_source(family).is_some();
+            let new = crate::classify(family, self.watcher.addresses(), default_route);
+            let old = std::mem::replace(&mut self.connectivity[family as usize], new);
+            if old != new {
+                self.queue.push_back(ConnectivityEvent { family, old, new });
+            }
+        }
+    }
+
+    /// Poll for a change of the connectivity.
+    pub fn poll_connectivity_event(&mut self, cx: &mut Context) -> Poll<Result<ConnectivityEvent>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            if !self.synced {
+                ready!(self.watcher.poll_synced(cx))?;
+                self.synced = true;
+            } else {
+                ready!(self.watcher.poll_address_event(cx))?;
+            }
+            self.update();
+        }
+    }
+}
+
+impl Stream for ConnectivityWatcher {
+    type Item = Result<ConnectivityEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_connectivity_event(cx).map(Some)
+    }
+}
+
+impl FusedStream for ConnectivityWatcher {
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,252 @@
+//! C API for the `cdylib` build.
+//!
+//! The watcher runs on a background thread. Events are either queued and
+//! retrieved with `if_watch_next` or delivered to a callback registered with
+//! `if_watch_set_callback`. The header is generated with
+//! `cbindgen --config cbindgen.toml --output include/if_watch.h src/ffi.rs`.
+use crate::blocking::{BlockingWatcher, Callback};
+use crate::{IfAddress, IfAddressEvent};
+use std::ffi::c_void;
+use std::net::IpAddr;
+use std::os::raw::c_int;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Size of the buffer holding the nul terminated interface name.
+pub const IF_WATCH_NAME_LEN: usize = 256;
+
+/// Kind of an address event.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+// The variants share the namespace of all identifiers in C.
+#[allow(clippy::enum_variant_names)]
+pub enum IfWatchEventKind {
+    /// A new local address has been added.
+    IfWatchUp = 0,
+    /// A local address has been deleted.
+    IfWatchDown = 1,
+    /// The flags of a local address have changed. Only reported on Linux.
+    IfWatchChanged = 2,
+}
+
+/// A local address together with the interface it is assigned to.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IfWatchAddress {
+    /// Address family, `4` or `6`.
+    pub family: u8,
+    /// Address in network byte order. IPv4 addresses use the first 4 bytes.
+    pub addr: [u8; 16],
+    /// Prefix length of the network.
+    pub prefix_len: u8,
+    /// Index of the interface, `0` if unknown.
+    pub if_index: u32,
+    /// Nul terminated name of the interface, truncated if necessary.
+    pub if_name: [u8; IF_WATCH_NAME_LEN],
+}
+
+/// An address change event.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IfWatchEvent {
+    /// Whether the address has been added, deleted or changed.
+    pub kind: IfWatchEventKind,
+    /// The address.
+    pub address: IfWatchAddress,
+}
+
+/// Callback invoked on the watcher thread for every event.
+pub type IfWatchCallback =
+    Option<unsafe extern "C" fn(event: *const IfWatchEvent, user: *mut c_void)>;
+
+/// Opaque watcher handle.
+pub struct IfWatchHandle(BlockingWatcher);
+
+struct UserData(*mut c_void);
+
+// The user data is only handed back to the callback registered with it.
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+impl UserData {
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+impl From<&IfAddress> for IfWatchAddress {
+    fn from(addr: &IfAddress) -> Self {
+        let (family, octets) = match addr.net.addr() {
+            IpAddr::V4(ip) => {
+                let mut octets = [0; 16];
+                octets[..4].copy_from_slice(&ip.octets());
+                (4, octets)
+            }
+            IpAddr::V6(ip) => (6, ip.octets()),
+        };
+        let mut if_name = [0; IF_WATCH_NAME_LEN];
+        let name = addr.if_name.as_bytes();
+        let len = name.len().min(IF_WATCH_NAME_LEN - 1);
+        if_name[..len].copy_from_slice(&name[..len]);
+        Self {
+            family,
+            addr: octets,
+            prefix_len: addr.net.prefix_len(),
+            if_index: addr.if_index.unwrap_or(0),
+            if_name,
+        }
+    }
+}
+
+impl From<&IfAddressEvent> for IfWatchEvent {
+    fn from(event: &IfAddressEvent) -> Self {
+        let kind = match event {
+            IfAddressEvent::Up(_) => IfWatchEventKind::IfWatchUp,
+            IfAddressEvent::Down(_) => IfWatchEventKind::IfWatchDown,
+            IfAddressEvent::Changed { .. } => IfWatchEventKind::IfWatchChanged,
+        };
+        Self {
+            kind,
+            address: event.address().into(),
+        }
+    }
+}
+
+/// Creates a watcher and waits until the current addresses have been loaded.
+///
+/// Returns `NULL` if the watcher could not be created. The watcher must be
+/// released with `if_watch_free`.
+#[no_mangle]
+pub extern "C" fn if_watch_new() -> *mut IfWatchHandle {
+    match BlockingWatcher::new() {
+        Ok(watcher) => Box::into_raw(Box::new(IfWatchHandle(watcher))),
+        Err(err) => {
+            log::error!("error creating watcher: {}", err);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Waits for the next event and writes it to `event`.
+///
+/// A negative `timeout_ms` waits indefinitely. Returns `1` if an event has
+/// been written, `0` on timeout and `-1` if the watcher failed.
+///
+/// # Safety
+///
+/// `watcher` must have been returned by `if_watch_new` and `event` must point
+/// to writable memory for an `IfWatchEvent`.
+#[no_mangle]
+pub unsafe extern "C" fn if_watch_next(
+    watcher: *mut IfWatchHandle,
+    event: *mut IfWatchEvent,
+    timeout_ms: c_int,
+) -> c_int {
+    let (Some(watcher), false) = (watcher.as_ref(), event.is_null()) else {
+        return -1;
+    };
+    let timeout = u64::try_from(timeout_ms).ok().map(Duration::from_millis);
+    match watcher.0.next(timeout) {
+        Ok(Some(next)) => {
+            event.write(IfWatchEvent::from(&next));
+            1
+        }
+        Ok(None) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Registers a callback invoked on the watcher thread for every event.
+///
+/// Events are no longer queued for `if_watch_next` while a callback is
+/// registered. Passing `NULL` as `callback` removes the callback.
+///
+/// # Safety
+///
+/// `watcher` must have been returned by `if_watch_new`. `callback` must be
+/// safe to call from another thread with `user` until it is replaced or the
+/// watcher is freed.
+#[no_mangle]
+pub unsafe extern "C" fn if_watch_set_callback(
+    watcher: *mut IfWatchHandle,
+    callback: IfWatchCallback,
+    user: *mut c_void,
+) {
+    if let Some(watcher) = watcher.as_ref() {
+        let user = UserData(user);
+        watcher.0.set_callback(callback.map(|callback| -> Callback {
+            Arc::new(move |event| {
+                let event = IfWatchEvent::from(event);
+                unsafe { callback(&event, user.get()) }
+            })
+        }));
+    }
+}
+
+/// Copies up to `len` current addresses to `addrs`.
+///
+/// Returns the total number of current addresses, which may be larger than
+/// `len`. `addrs` may be `NULL` if `len` is `0`.
+///
+/// # Safety
+///
+/// `watcher` must have been returned by `if_watch_new` and `addrs` must point
+/// to writable memory for `len` `IfWatchAddress` values.
+#[no_mangle]
+pub unsafe extern "C" fn if_watch_addresses(
+    watcher: *mut IfWatchHandle,
+    addrs: *mut IfWatchAddress,
+    len: usize,
+) -> usize {
+    let Some(watcher) = watcher.as_ref() else {
+        return 0;
+    };
+    let current = watcher.0.addresses();
+    if !addrs.is_null() {
+        for (i, addr) in current.iter().take(len).enumerate() {
+            addrs.add(i).write(addr.into());
+        }
+    }
+    current.len()
+}
+
+/// Stops the watcher and releases its resources.
+///
+/// # Safety
+///
+/// `watcher` must have been returned by `if_watch_new` or be `NULL`, and must
+/// not be used afterwards. Must not be called from the callback.
+#[no_mangle]
+pub unsafe extern "C" fn if_watch_free(watcher: *mut IfWatchHandle) {
+    if !watcher.is_null() {
+        drop(Box::from_raw(watcher));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi_watcher() {
+        unsafe {
+            let watcher = if_watch_new();
+            assert!(!watcher.is_null());
+
+            let len = if_watch_addresses(watcher, std::ptr::null_mut(), 0);
+            let mut addrs = vec![std::mem::zeroed::<IfWatchAddress>(); len];
+            assert_eq!(if_watch_addresses(watcher, addrs.as_mut_ptr(), len), len);
+
+            if len > 0 {
+                let mut event = std::mem::zeroed::<IfWatchEvent>();
+                assert_eq!(if_watch_next(watcher, &mut event, 1000), 1);
+                assert_eq!(event.kind, IfWatchEventKind::IfWatchUp);
+                assert!(addrs
+                    .iter()
+                    .any(|addr| addr.addr == event.address.addr
+                        && addr.if_name == event.address.if_name));
+            }
+            if_watch_free(watcher);
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! Filtering of addresses by their interface.
+use crate::{IfAddress, IfKind, IfLink};
+#[cfg(any(feature = "smol", feature = "tokio"))]
+use crate::{IfAddressEvent, StampedEvent};
+#[cfg(any(feature = "smol", feature = "tokio"))]
+use fnv::FnvHashSet;
+#[cfg(any(feature = "smol", feature = "tokio"))]
+use ipnet::IpNet;
+use std::sync::Arc;
+
+/// Name patterns of the bridges and virtual Ethernet devices created by
+/// container runtimes and virtual machine managers.
+const CONTAINER_PATTERNS: &[&str] = &[
+    "docker*",
+    "br-*",
+    "veth*",
+    "virbr*",
+    "cni*",
+    "flannel*",
+    "lxcbr*",
+    "lxdbr*",
+    "cali*",
+    "vxlan.calico",
+    "weave*",
+    "kube-*",
+    "podman*",
+    "vmnet*",
+    "vboxnet*",
+];
+
+/// Name patterns of VPN interfaces whose kind cannot be told apart from the
+/// kind of other interfaces.
+const VPN_PATTERNS: &[&str] = &[
+    "tun*",
+    "utun*",
+    "tap*",
+    "wg*",
+    "tailscale*",
+    "zt*",
+    "nordlynx",
+    "ipsec*",
+];
+
+/// A named set of interfaces to exclude, see [`IfFilter::preset`].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum FilterPreset {
+    /// Only include Ethernet and wireless LAN interfaces as well as the bonds
+    /// and VLANs built on them, excluding loopback, bridges, virtual and VPN
+    /// interfaces. Interfaces of unknown kind are included unless their name
+    /// matches one of the other presets.
+    PhysicalOnly,
+    /// Exclude the bridges and virtual Ethernet devices of container runtimes
+    /// and virtual machine managers, such as `docker0`, `br-*`, `veth*`,
+    /// `virbr*`, `cni*`, `flannel*` and `lxcbr*`.
+    ExcludeContainerBridges,
+    /// Exclude `tun`, `tap`, WireGuard and tunnel interfaces as well as the
+    /// interfaces of common VPN clients, such as `tailscale*` and `zt*`.
+    ExcludeVpn,
+}
+
+impl FilterPreset {
+    fn excludes(self, addr: &IfAddress, kind: Option<&IfKind>) -> bool {
+        let name = addr.if_name.as_str();
+        let vpn = matches!(
+            kind,
+            Some(IfKind::Tun | IfKind::Tap | IfKind::WireGuard | IfKind::Tunnel)
+        ) || matches_any(VPN_PATTERNS, name);
+        let container = matches!(kind, Some(IfKind::Veth)) || matches_any(CONTAINER_PATTERNS, name);
+        match self {
+            Self::PhysicalOnly => match kind {
+                Some(IfKind::Ethernet | IfKind::Wlan | IfKind::Bond | IfKind::Vlan) => {
+                    vpn || container
+                }
+                None => vpn || container || addr.net.addr().is_loopback(),
+                Some(_) => true,
+            },
+            Self::ExcludeContainerBridges => container,
+            Self::ExcludeVpn => vpn,
+        }
+    }
+}
+
+type Predicate = Arc<dyn Fn(&IfAddress, Option<&IfLink>) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+enum Rule {
+    Preset(FilterPreset),
+    Name(String),
+    Custom(Predicate),
+}
+
+/// Excludes addresses based on their interface, see `IfWatcher::set_filter`.
+///
+/// The filter is built from presets, interface name patterns and custom rules.
+/// An address is included unless any of them excludes it. Rules are evaluated
+/// with the properties of the interface, if the watcher knows them.
+#[derive(Clone, Default)]
+pub struct IfFilter {
+    rules: Vec<Rule>,
+}
+
+impl std::fmt::Debug for IfFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut list = f.debug_list();
+        for rule in &self.rules {
+            match rule {
+                Rule::Preset(preset) => list.entry(preset),
+                Rule::Name(pattern) => list.entry(pattern),
+                Rule::Custom(_) => list.entry(&format_args!("<custom>")),
+            };
+        }
+        list.finish()
+    }
+}
+
+impl IfFilter {
+    /// Create a filter including all addresses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Exclude the interfaces of `preset`.
+    pub fn preset(mut self, preset: FilterPreset) -> Self {
+        self.rules.push(Rule::Preset(preset));
+        self
+    }
+
+    /// Exclude interfaces whose name matches `pattern`, in which `*` matches
+    /// any sequence of characters, e.g. `br-*`.
+    pub fn exclude_name(mut self, pattern: impl Into<String>) -> Self {
+        self.rules.push(Rule::Name(pattern.into()));
+        self
+    }
+
+    /// Exclude addresses for which `predicate` returns `true`. It is called
+    /// with the address and its interface, if known.
+    pub fn exclude<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&IfAddress, Option<&IfLink>) -> bool + Send + Sync + 'static,
+    {
+        self.rules.push(Rule::Custom(Arc::new(predicate)));
+        self
+    }
+
+    /// Whether `addr` on the interface `link` is included.
+    pub fn matches(&self, addr: &IfAddress, link: Option<&IfLink>) -> bool {
+        let kind = link.and_then(|link| link.kind.as_ref());
+        !self.rules.iter().any(|rule| match rule {
+            Rule::Preset(preset) => preset.excludes(addr, kind),
+            Rule::Name(pattern) => glob_match(pattern, &addr.if_name),
+            Rule::Custom(predicate) => predicate(addr, link),
+        })
+    }
+}
+
+/// The filter of a watcher, which decides whether an event is included when
+/// it is queued. The addresses that have been reported up are tracked, so
+/// that every `Down` event follows an `Up` event of its address, even if the
+/// filter or the interface of the address changed in between.
+#[cfg(any(feature = "smol", feature = "tokio"))]
+#[derive(Debug, Default)]
+pub(crate) struct FilterState {
+    filter: IfFilter,
+    included: FnvHashSet<IpNet>,
+}
+
+#[cfg(any(feature = "smol", feature = "tokio"))]
+impl FilterState {
+    /// Stamps `event` of an address on the interface `link`, deciding whether
+    /// it is included.
+    pub(crate) fn stamp(
+        &mut self,
+        event: IfAddressEvent,
+        link: Option<&IfLink>,
+        kernel_seq: Option<u32>,
+    ) -> StampedEvent {
+        let included = match &event {
+            IfAddressEvent::Up(addr) => {
+                self.filter.matches(addr, link) && self.included.insert(addr.net)
+            }
+            IfAddressEvent::Down(addr) => self.included.remove(&addr.net),
+            IfAddressEvent::Changed { new, .. } => self.included.contains(&new.net),
+        };
+        StampedEvent::new(event, kernel_seq, included)
+    }
+
+    /// Whether `addr` has been reported up.
+    pub(crate) fn contains(&self, addr: &IfAddress) -> bool {
+        self.included.contains(&addr.net)
+    }
+
+    /// Replaces the filter, returning `Up` and `Down` events for the current
+    /// addresses `addrs` whose inclusion changed.
+    pub(crate) fn set_filter<'a>(
+        &mut self,
+        filter: IfFilter,
+        addrs: impl Iterator<Item = (&'a IfAddress, Option<&'a IfLink>)>,
+    ) -> Vec<StampedEvent> {
+        self.filter = filter;
+        let mut events = Vec::new();
+        for (addr, link) in addrs {
+            let event = match (self.contains(addr), self.filter.matches(addr, link)) {
+                (false, true) => IfAddressEvent::Up(addr.clone()),
+                (true, false) => IfAddressEvent::Down(addr.clone()),
+                _ => continue,
+            };
+            events.push(self.stamp(event, link, None));
+        }
+        events
+    }
+}
+
+fn matches_any(patterns: &[&str], name: &str) -> bool {
+    patterns.iter().any(|pattern| glob_match(pattern, name))
+}
+
+/// Matches `name` against `pattern`, in which `*` matches any sequence of
+/// characters.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            name.char_indices()
+                .map(|(i, _)| i)
+                .chain(Some(name.len()))
+                .any(|i| glob_match(rest, &name[i..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, FilterPreset, IfFilter};
+    use crate::{IfAddress, IfKind, IfLink};
+
+    fn link(if_name: &str, kind: Option<IfKind>) -> IfLink {
+        IfLink {
+            if_index: Some(2),
+            if_name: if_name.into(),
+            mtu: None,
+            mac: None,
+            link_type: None,
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("br-*", "br-1234"));
+        assert!(glob_match("br-*", "br-"));
+        assert!(!glob_match("br-*", "br0"));
+        assert!(glob_match("*.calico", "vxlan.calico"));
+        assert!(glob_match("a*b*c", "aXbYbc"));
+        assert!(!glob_match("a*b*c", "aXbY"));
+        assert!(glob_match("eth0", "eth0"));
+        assert!(!glob_match("eth0", "eth01"));
+    }
+
+    #[test]
+    fn test_filter() {
+        use FilterPreset::*;
+        use IfKind::*;
+
+        let cases = [
+            (PhysicalOnly, "eth0", Some(Ethernet), true),
+            (PhysicalOnly, "wlan0", Some(Wlan), true),
+            (PhysicalOnly, "bond0", Some(Bond), true),
+            (PhysicalOnly, "eth0.100", Some(Vlan), true),
+            (PhysicalOnly, "lo", Some(Loopback), false),
+            (PhysicalOnly, "br0", Some(Bridge), false),
+            (PhysicalOnly, "docker0", Some(Bridge), false),
+            (PhysicalOnly, "tailscale0", Some(Tun), false),
+            (PhysicalOnly, "Ethernet 2", None, true),
+            (PhysicalOnly, "veth1234", None, false),
+            (ExcludeContainerBridges, "docker0", Some(Bridge), false),
+            (ExcludeContainerBridges, "br-1a2b3c", Some(Bridge), false),
+            (ExcludeContainerBridges, "br0", Some(Bridge), true),
+            (ExcludeContainerBridges, "eth1", Some(Veth), false),
+            (ExcludeContainerBridges, "wg0", Some(WireGuard), true),
+            (ExcludeVpn, "wg0", Some(WireGuard), false),
+            (ExcludeVpn, "vpn0", Some(Tun), false),
+            (ExcludeVpn, "utun3", None, false),
+            (ExcludeVpn, "eth0", Some(Ethernet), true),
+        ];
+        for (preset, name, kind, included) in cases {
+            let filter = IfFilter::new().preset(preset);
+            let link = link(name, kind);
+            assert_eq!(
+                filter.matches(&IfAddress::test("10.0.0.2/24", 2, name), Some(&link)),
+                included,
+                "{:?} {:?}",
+                preset,
+                link
+            );
+        }
+
+        let filter = IfFilter::new()
+            .preset(ExcludeVpn)
+            .exclude_name("eth1")
+            .exclude(|addr, _| addr.net.addr().is_ipv6());
+        assert!(filter.matches(&IfAddress::test("10.0.0.2/24", 2, "eth0"), None));
+        assert!(!filter.matches(&IfAddress::test("10.0.0.2/24", 2, "eth1"), None));
+        assert!(!filter.matches(&IfAddress::test("10.0.0.2/24", 2, "wg0"), None));
+        let v6 = IfAddress::test("2001:db8::1/64", 2, "eth0");
+        assert!(!filter.matches(&v6, None));
+    }
+
+    #[cfg(any(feature = "smol", feature = "tokio"))]
+    #[test]
+    fn test_filter_state() {
+        use super::FilterState;
+        use crate::IfAddressEvent::{self, Down, Up};
+
+        let eth0 = IfAddress::test("10.0.0.2/24", 2, "eth0");
+        let docker0 = IfAddress::test("172.17.0.1/16", 2, "docker0");
+        let returned = |events: Vec<crate::StampedEvent>| -> Vec<IfAddressEvent> {
+            let events = events.into_iter().filter(|stamped| stamped.included);
+            events.map(|stamped| stamped.event).collect()
+        };
+        let mut state = FilterState::default();
+        let stamped = state.stamp(Up(docker0.clone()), None, None);
+        assert!(stamped.included);
+        state.stamp(Up(eth0.clone()), None, None);
+        let addrs = [&eth0, &docker0].map(|addr| (addr, None));
+        let filter = IfFilter::new().exclude_name("docker*");
+        let events = state.set_filter(filter, addrs.into_iter());
+        assert_eq!(returned(events), [Down(docker0.clone())]);
+        // The address has already been reported down.
+        let stamped = state.stamp(Down(docker0.clone()), None, None);
+        assert!(!stamped.included);
+        state.stamp(Up(docker0.clone()), None, None);
+        let events = state.set_filter(IfFilter::new(), addrs.into_iter());
+        assert_eq!(returned(events), [Up(docker0.clone())]);
+        // The filter no longer matters once an address has been reported up.
+        state.set_filter(IfFilter::new().exclude_name("eth*"), std::iter::empty());
+        let stamped = state.stamp(Down(eth0.clone()), None, None);
+        assert!(stamped.included);
+    }
+}
@@ -0,0 +1,375 @@
+//! Watching of addresses with `if_addrs`.
+//!
+//! The notifications of these platforms do not say what changed, so the
+//! current addresses are compared to the known ones whenever the notifier of
+//! the platform backend reports a change.
+#[cfg(any(target_os = "ios", target_os = "macos"))]
+use crate::apple as platform;
+#[cfg(not(any(
+    target_os = "ios",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "windows",
+)))]
+use crate::fallback as platform;
+use crate::filter::FilterState;
+#[cfg(target_os = "windows")]
+use crate::win as platform;
+use crate::{
+    IfAddress, IfAddressEvent, IfEvent, IfFilter, IfFlags, IfInterface, IfLink, IfLinkEvent,
+    LinkType, StampedEvent,
+};
+use fnv::FnvHashMap;
+use futures::ready;
+use futures::stream::{FusedStream, Stream};
+use if_addrs::{IfAddr, Interface};
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use platform::Notifier;
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+use platform::BACKEND;
+use std::collections::hash_map::Entry;
+use std::collections::VecDeque;
+use std::io::Result;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// An address set/watcher
+#[derive(Debug)]
+pub struct IfWatcher {
+    addrs: FnvHashMap<IpNet, IfAddress>,
+    queue: VecDeque<StampedEvent>,
+    seq: u64,
+    links: FnvHashMap<String, IfLink>,
+    link_queue: VecDeque<IfLinkEvent>,
+    link_events: bool,
+    filter: FilterState,
+    notifier: Notifier,
+}
+
+impl IfWatcher {
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        #[cfg(feature = "tracing")]
+        crate::trace::setup(BACKEND, None);
+        let mut watcher = Self {
+            addrs: Default::default(),
+            queue: Default::default(),
+            seq: 0,
+            links: Default::default(),
+            link_queue: Default::default(),
+            link_events: false,
+            filter: Default::default(),
+            notifier: Notifier::new()?,
+        };
+        watcher.resync()?;
+        Ok(watcher)
+    }
+
+    fn resync(&mut self) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let addrs = if_addrs::get_if_addrs();
+        #[cfg(feature = "metrics")]
+        if let Err(err) = &addrs {
+            crate::stats::error(BACKEND, err);
+        }
+        let addrs = addrs?;
+        #[cfg(feature = "tracing")]
+        let queued = self.queue.len();
+        self.resync_links(&addrs);
+        for old_addr in self.addrs.clone().into_values() {
+            if !addrs.iter().any(|addr| addr.ip() == old_addr.net.addr()) {
+                self.addrs.remove(&old_addr.net);
+                let event = IfAddressEvent::Down(old_addr);
+                self.queue.push_back(self.filter.stamp(event, None, None));
+            }
+        }
+        for new_addr in addrs {
+            let addr = interface_to_address(new_addr);
+            if let Entry::Vacant(entry) = self.addrs.entry(addr.net) {
+                let addr = entry.insert(addr);
+                let link = self.links.get(&addr.if_name);
+                let event = IfAddressEvent::Up(addr.clone());
+                self.queue.push_back(self.filter.stamp(event, link, None));
+            }
+        }
+        #[cfg(feature = "tracing")]
+        {
+            let events = self.queue.range(queued..);
+            let up = events
+                .clone()
+                .filter(|stamped| matches!(stamped.event, IfAddressEvent::Up(_)));
+            let down = events.filter(|stamped| matches!(stamped.event, IfAddressEvent::Down(_)));
+            crate::trace::resync(BACKEND, up.count(), down.count());
+        }
+        #[cfg(feature = "metrics")]
+        {
+            crate::stats::resync(BACKEND, started.elapsed());
+            crate::stats::queue(BACKEND, self.queue.len());
+        }
+        Ok(())
+    }
+
+    fn resync_links(&mut self, addrs: &[Interface]) {
+        let links = interfaces_to_links(addrs);
+        let (queue, link_events) = (&mut self.link_queue, self.link_events);
+        self.links.retain(|name, link| {
+            let keep = links.contains_key(name);
+            if !keep && link_events {
+                queue.push_back(IfLinkEvent::Removed(link.clone()));
+            }
+            keep
+        });
+        for (name, link) in links {
+            let event = match self.links.entry(name) {
+                Entry::Vacant(entry) => IfLinkEvent::Added(entry.insert(link).clone()),
+                Entry::Occupied(entry) if *entry.get() == link => continue,
+                Entry::Occupied(mut entry) => IfLinkEvent::Changed {
+                    old: entry.insert(link.clone()),
+                    new: link,
+                },
+            };
+            if link_events {
+                self.link_queue.push_back(event);
+            }
+        }
+    }
+
+    /// Iterate over current networks.
+    pub fn iter(&self) -> impl Iterator<Item = &IpNet> {
+        self.addresses().map(|addr| &addr.net)
+    }
+
+    /// Iterate over current addresses and their interfaces.
+    pub fn addresses(&self) -> impl Iterator<Item = &IfAddress> {
+        self.addrs
+            .values()
+            .filter(|addr| self.filter.contains(addr))
+    }
+
+    /// Exclude addresses from the events and iterators of the watcher based on
+    /// their interface. Events are filtered when they are queued, and `Up` and
+    /// `Down` events are queued for the current addresses whose inclusion
+    /// changed, so every `Down` event follows an `Up` event of its address.
+    pub fn set_filter(&mut self, filter: IfFilter) {
+        let links = &self.links;
+        let addrs = self
+            .addrs
+            .values()
+            .map(|addr| (addr, links.get(&addr.if_name)));
+        let events = self.filter.set_filter(filter, addrs);
+        self.queue.extend(events);
+    }
+
+    /// Iterate over current interfaces.
+    pub fn links(&self) -> impl Iterator<Item = &IfLink> {
+        self.links.values()
+    }
+
+    /// Flags of the interface with index `if_index`. Only available on Linux.
+    pub fn link_flags(&self, _if_index: u32) -> Option<IfFlags> {
+        None
+    }
+
+    /// Select the best current address for sending to `dst`, see
+    /// [`select_source`](crate::select_source).
+    pub fn select_source(&self, dst: IpAddr) -> Option<&IfAddress> {
+        crate::select_source(dst, self.addresses())
+    }
+
+    /// Snapshot of the current interfaces and their addresses.
+    pub fn interfaces(&self) -> Vec<IfInterface> {
+        let links = self.links.values().map(|link| (link, None));
+        crate::group_interfaces(links, self.addresses())
+    }
+
+    /// Poll until the addresses present when the watcher was created have been
+    /// loaded. Events for these addresses remain queued.
+    pub fn poll_synced(&mut self, _cx: &mut Context) -> Poll<Result<()>> {
+        // The initial addresses are loaded when the watcher is created.
+        Poll::Ready(Ok(()))
+    }
+
+    /// Poll for an address change event including the interface of the
+    /// address.
+    pub fn poll_address_event(&mut self, cx: &mut Context) -> Poll<Result<IfAddressEvent>> {
+        self.poll_stamped_event(cx).map_ok(|stamped| stamped.event)
+    }
+
+    /// Poll for an address change event with the time it was received and its
+    /// sequence number.
+    pub fn poll_stamped_event(&mut self, cx: &mut Context) -> Poll<Result<StampedEvent>> {
+        loop {
+            if let Some(mut stamped) = self.queue.pop_front() {
+                if stamped.included {
+                    stamped.seq = self.seq;
+                    self.seq += 1;
+                    #[cfg(feature = "tracing")]
+                    crate::trace::event(BACKEND, &stamped);
+                    #[cfg(feature = "metrics")]
+                    {
+                        crate::stats::event(BACKEND, &stamped);
+                        crate::stats::queue(BACKEND, self.queue.len());
+                    }
+                    return Poll::Ready(Ok(stamped));
+                }
+                continue;
+            }
+            if self.notifier.poll_change(cx).is_pending() {
+                return Poll::Pending;
+            }
+            if let Err(err) = self.resync() {
+                return Poll::Ready(Err(err));
+            }
+        }
+    }
+
+    /// Poll for a link change event.
+    ///
+    /// Link events are only recorded once this method has been called, so
+    /// that watchers only interested in addresses do not accumulate them. The
+    /// first call queues an `Added` event for every current interface.
+    pub fn poll_link_event(&mut self, cx: &mut Context) -> Poll<Result<IfLinkEvent>> {
+        if !self.link_events {
+            self.link_events = true;
+            let links = self.links.values().cloned().map(IfLinkEvent::Added);
+            self.link_queue.extend(links);
+        }
+        loop {
+            if let Some(event) = self.link_queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            if self.notifier.poll_change(cx).is_pending() {
+                return Poll::Pending;
+            }
+            if let Err(err) = self.resync() {
+                return Poll::Ready(Err(err));
+            }
+        }
+    }
+
+    /// Poll for an address change event. Changes of the flags of an address
+    /// are skipped.
+    pub fn poll_if_event(&mut self, cx: &mut Context) -> Poll<Result<IfEvent>> {
+        loop {
+            let event = ready!(self.poll_address_event(cx))?;
+            if let Some(event) = event.to_if_event() {
+                return Poll::Ready(Ok(event));
+            }
+        }
+    }
+}
+
+impl Stream for IfWatcher {
+    type Item = Result<IfEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_if_event(cx).map(Some)
+    }
+}
+
+impl FusedStream for IfWatcher {
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
+
+impl crate::AddressWatcher for IfWatcher {
+    fn poll_address_event(&mut self, cx: &mut Context) -> Poll<Result<IfAddressEvent>> {
+        IfWatcher::poll_address_event(self, cx)
+    }
+}
+
+/// Returns the current addresses, sorted.
+///
+/// The addresses are loaded like the initial addresses of an `IfWatcher`, so
+/// they match the addresses of its events.
+pub async fn current_addresses() -> Result<Vec<IfAddress>> {
+    let mut addrs = FnvHashMap::<IpNet, IfAddress>::default();
+    for iface in if_addrs::get_if_addrs()? {
+        let addr = interface_to_address(iface);
+        addrs.entry(addr.net).or_insert(addr);
+    }
+    let mut addrs: Vec<_> = addrs.into_values().collect();
+    addrs.sort();
+    Ok(addrs)
+}
+
+fn interface_to_address(iface: Interface) -> IfAddress {
+    IfAddress {
+        net: ifaddr_to_ipnet(iface.addr),
+        if_index: iface.index,
+        if_name: iface.name,
+        netns: None,
+        flags: None,
+    }
+}
+
+/// The links of the interfaces of `addrs`. Only the loopback interface is
+/// reported with a link type, since `if_addrs` does not report the hardware.
+fn interfaces_to_links(addrs: &[Interface]) -> FnvHashMap<String, IfLink> {
+    let mut links = FnvHashMap::<String, IfLink>::default();
+    for iface in addrs {
+        let link = links
+            .entry(iface.name.clone())
+            .or_insert_with(|| interface_to_link(iface));
+        // The loopback interface also has addresses such as `fe80::1`.
+        if iface.is_loopback() {
+            *link = interface_to_link(iface);
+        }
+    }
+    links
+}
+
+fn interface_to_link(iface: &Interface) -> IfLink {
+    IfLink {
+        if_index: iface.index,
+        if_name: iface.name.clone(),
+        mtu: None,
+        mac: None,
+        link_type: iface.is_loopback().then_some(LinkType::Loopback),
+        kind: crate::kind_from_name(&iface.name, iface.is_loopback()),
+    }
+}
+
+fn ifaddr_to_ipnet(addr: IfAddr) -> IpNet {
+    match addr {
+        IfAddr::V4(ip) => {
+            let prefix_len = (!u32::from_be_bytes(ip.netmask.octets())).leading_zeros();
+            IpNet::V4(
+                Ipv4Net::new(ip.ip, prefix_len as u8).expect("if_addrs returned a valid prefix"),
+            )
+        }
+        IfAddr::V6(ip) => {
+            let prefix_len = (!u128::from_be_bytes(ip.netmask.octets())).leading_zeros();
+            IpNet::V6(
+                Ipv6Net::new(ip.ip, prefix_len as u8).expect("if_addrs returned a valid prefix"),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::interfaces_to_links;
+    use crate::{IfKind, LinkType};
+    use if_addrs::{IfAddr, Ifv6Addr, Interface};
+
+    #[test]
+    fn test_interfaces_to_links() {
+        let iface = |name: &str, ip: &str| Interface {
+            name: name.into(),
+            addr: IfAddr::V6(Ifv6Addr {
+                ip: ip.parse().unwrap(),
+                netmask: "ffff:ffff:ffff:ffff::".parse().unwrap(),
+                broadcast: None,
+            }),
+            index: Some(1),
+        };
+        let links = interfaces_to_links(&[iface("lo0", "fe80::1"), iface("lo0", "::1")]);
+        assert_eq!(links["lo0"].link_type, Some(LinkType::Loopback));
+        assert_eq!(links["lo0"].kind, Some(IfKind::Loopback));
+        let links = interfaces_to_links(&[iface("en0", "fe80::2")]);
+        assert_eq!(links["en0"].link_type, None);
+    }
+}
@@ -2,22 +2,60 @@
 #![deny(missing_docs)]
 #![deny(warnings)]
 
+pub use filter::{FilterPreset, IfFilter};
 pub use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+pub use listener::{AddressWatcher, ListenerEvent, ListenerManager};
+pub use multicast::MulticastJoiner;
+pub use prefix::PrefixTracker;
+pub use select::select_source;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[cfg(target_os = "macos")]
+#[cfg(any(feature = "smol", feature = "tokio"))]
 mod apple;
 #[cfg(target_os = "ios")]
+#[cfg(any(feature = "smol", feature = "tokio"))]
 mod apple;
+#[cfg(any(feature = "ffi", feature = "python"))]
+mod blocking;
+#[cfg(not(target_os = "linux"))]
+#[cfg(any(feature = "smol", feature = "tokio"))]
+mod connectivity;
 #[cfg(not(any(
     target_os = "ios",
     target_os = "linux",
     target_os = "macos",
     target_os = "windows",
 )))]
+#[cfg(any(feature = "smol", feature = "tokio"))]
 mod fallback;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod filter;
+#[cfg(not(target_os = "linux"))]
+#[cfg(any(feature = "smol", feature = "tokio"))]
+mod ifaddrs;
 #[cfg(target_os = "linux")]
+#[cfg(any(feature = "smol", feature = "tokio"))]
 mod linux;
+mod listener;
+mod multicast;
+mod prefix;
+#[cfg(not(target_os = "linux"))]
+#[cfg(any(feature = "smol", feature = "tokio"))]
+mod primary;
+#[cfg(feature = "python")]
+mod python;
+mod select;
+#[cfg(feature = "metrics")]
+#[cfg(any(feature = "smol", feature = "tokio"))]
+mod stats;
+#[cfg(feature = "tracing")]
+#[cfg(any(feature = "smol", feature = "tokio"))]
+mod trace;
 #[cfg(target_os = "windows")]
+#[cfg(any(feature = "smol", feature = "tokio"))]
 mod win;
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
@@ -63,7 +101,13 @@ pub use linux::tokio;
 pub use linux::smol;
 
 /// An address change event.
+///
+/// With the `serde` feature enabled the event serializes as an externally
+/// tagged enum whose content is the network in CIDR notation, e.g.
+/// `{"Up":"192.168.1.2/24"}` in JSON. Non human-readable formats use the
+/// compact representation of [`IpNet`].
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum IfEvent {
     /// A new local address has been added.
     Up(IpNet),
@@ -71,6 +115,477 @@ pub enum IfEvent {
     Down(IpNet),
 }
 
+/// A local address together with the interface it is assigned to.
+///
+/// With the `serde` feature enabled the record serializes as a struct with the
+/// fields named as below, e.g.
+/// `{"net":"192.168.1.2/24","if_index":2,"if_name":"eth0"}` in JSON. `netns`
+/// and `flags` are omitted when they are `None`.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IfAddress {
+    /// The address and its prefix length.
+    pub net: IpNet,
+    /// Index of the interface, if the platform reports one.
+    pub if_index: Option<u32>,
+    /// Name of the interface.
+    pub if_name: String,
+    /// Inode number of the Linux network namespace of the interface, as shown
+    /// by `readlink /proc/self/ns/net`. `None` on other platforms.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub netns: Option<u64>,
+    /// Flags of the address. Only available on Linux.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub flags: Option<IfAddressFlags>,
+}
+
+/// Flags of an address.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IfAddressFlags {
+    /// The preferred lifetime of the address has expired. It remains valid
+    /// for existing connections but should not be used for new ones.
+    pub deprecated: bool,
+    /// The address is a temporary IPv6 privacy address (RFC 8981).
+    pub temporary: bool,
+    /// Duplicate address detection has not completed yet, so the address
+    /// cannot be used.
+    pub tentative: bool,
+}
+
+/// An address change event carrying the interface of the address.
+///
+/// Serializes like [`IfEvent`] with an [`IfAddress`] as content, e.g.
+/// `{"Up":{"net":"192.168.1.2/24","if_index":2,"if_name":"eth0"}}`, and
+/// `Changed` as a struct with the fields `old` and `new`.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum IfAddressEvent {
+    /// A new local address has been added.
+    Up(IfAddress),
+    /// A local address has been deleted.
+    Down(IfAddress),
+    /// The flags of a local address have changed, e.g. because it has been
+    /// deprecated or duplicate address detection has completed. Only reported
+    /// on Linux.
+    Changed {
+        /// The address before the change.
+        old: IfAddress,
+        /// The address after the change.
+        new: IfAddress,
+    },
+}
+
+#[cfg(test)]
+impl IfAddress {
+    /// An address of the interface `if_name` without namespace and flags.
+    pub(crate) fn test(net: &str, if_index: u32, if_name: &str) -> Self {
+        Self {
+            net: net.parse().unwrap(),
+            if_index: Some(if_index),
+            if_name: if_name.into(),
+            netns: None,
+            flags: None,
+        }
+    }
+}
+
+impl IfAddressEvent {
+    /// The address that has been added, deleted or changed.
+    pub fn address(&self) -> &IfAddress {
+        match self {
+            Self::Up(addr) | Self::Down(addr) | Self::Changed { new: addr, .. } => addr,
+        }
+    }
+
+    /// The event as an [`IfEvent`], which has no equivalent of `Changed`.
+    pub fn to_if_event(&self) -> Option<IfEvent> {
+        match self {
+            Self::Up(addr) => Some(IfEvent::Up(addr.net)),
+            Self::Down(addr) => Some(IfEvent::Down(addr.net)),
+            Self::Changed { .. } => None,
+        }
+    }
+}
+
+/// An address change event with the time it was received and its position
+/// among the events of its watcher, see `IfWatcher::poll_stamped_event`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct StampedEvent {
+    /// The event.
+    pub event: IfAddressEvent,
+    /// Number of the event among the events returned by its watcher, starting
+    /// at `0`.
+    pub seq: u64,
+    /// Monotonic time at which the watcher received the change.
+    pub time: std::time::Instant,
+    /// Sequence number of the netlink request that caused the change. Only
+    /// available on Linux for changes made through netlink, such as with
+    /// `ip address`, and not for the initial addresses.
+    pub kernel_seq: Option<u32>,
+    /// Whether the filter of the watcher included the event when it was
+    /// queued.
+    included: bool,
+}
+
+#[cfg(any(feature = "smol", feature = "tokio"))]
+impl StampedEvent {
+    /// Stamps `event` with the current time. The sequence number is assigned
+    /// when the event is returned.
+    fn new(event: IfAddressEvent, kernel_seq: Option<u32>, included: bool) -> Self {
+        Self {
+            event,
+            seq: 0,
+            time: std::time::Instant::now(),
+            kernel_seq,
+            included,
+        }
+    }
+}
+
+/// Link layer properties of an interface.
+///
+/// On platforms other than Linux only the name and index are known and the
+/// kind is guessed, and only interfaces with at least one address are reported.
+///
+/// With the `serde` feature enabled the record serializes as a struct with the
+/// fields named as below, with `mac` as an array of bytes.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IfLink {
+    /// Index of the interface, if the platform reports one.
+    pub if_index: Option<u32>,
+    /// Name of the interface.
+    pub if_name: String,
+    /// Maximum transmission unit in bytes. Only available on Linux.
+    pub mtu: Option<u32>,
+    /// Hardware address, if the interface has one. Only available on Linux.
+    pub mac: Option<Vec<u8>>,
+    /// Hardware type of the interface. Other platforms only report the
+    /// loopback interface.
+    pub link_type: Option<LinkType>,
+    /// Kind of the interface, see [`IfKind`].
+    pub kind: Option<IfKind>,
+}
+
+/// Hardware type of an interface.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LinkType {
+    /// Ethernet, including Wi-Fi and virtual Ethernet devices.
+    Ethernet,
+    /// The loopback interface.
+    Loopback,
+    /// A point-to-point protocol link.
+    Ppp,
+    /// An IP in IP, GRE or similar tunnel.
+    Tunnel,
+    /// A device carrying raw IP packets without a link layer header, such as a
+    /// `tun` device or WireGuard.
+    RawIp,
+    /// Any other platform specific hardware type, e.g. `ARPHRD_*` on Linux.
+    Other(u16),
+}
+
+/// Kind of an interface.
+///
+/// On Linux the kind is determined from the link info reported by the kernel,
+/// the hardware type and `/sys/class/net/NAME/wireless`. Other platforms guess
+/// it from the name of the interface, e.g. `utun0` or `Wi-Fi`.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum IfKind {
+    /// The loopback interface.
+    Loopback,
+    /// A wired Ethernet interface.
+    Ethernet,
+    /// A wireless LAN interface.
+    Wlan,
+    /// A bridge.
+    Bridge,
+    /// One end of a virtual Ethernet pair, as used by containers.
+    Veth,
+    /// A VLAN interface.
+    Vlan,
+    /// A bonded or teamed interface.
+    Bond,
+    /// A MAC VLAN interface.
+    Macvlan,
+    /// A `tun` device carrying IP packets, as used by most VPNs.
+    Tun,
+    /// A `tap` device carrying Ethernet frames.
+    Tap,
+    /// A WireGuard interface.
+    WireGuard,
+    /// An IP in IP, GRE, VXLAN or similar tunnel.
+    Tunnel,
+    /// A point-to-point protocol link.
+    Ppp,
+    /// Any other kind, e.g. the `IFLA_INFO_KIND` on Linux.
+    Other(String),
+}
+
+/// A link change event.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum IfLinkEvent {
+    /// A new interface has appeared.
+    Added(IfLink),
+    /// The name, MTU, hardware address or type of an interface has changed.
+    Changed {
+        /// Properties before the change.
+        old: IfLink,
+        /// Properties after the change.
+        new: IfLink,
+    },
+    /// An interface has disappeared.
+    Removed(IfLink),
+}
+
+impl IfLinkEvent {
+    /// The current properties of the interface, or the last known ones if it
+    /// has been removed.
+    pub fn link(&self) -> &IfLink {
+        match self {
+            Self::Added(link) | Self::Changed { new: link, .. } | Self::Removed(link) => link,
+        }
+    }
+}
+
+/// Interface flags.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IfFlags {
+    /// The interface has been enabled administratively.
+    pub up: bool,
+    /// The interface is operational, e.g. the cable is plugged in.
+    pub running: bool,
+    /// The interface is a loopback interface.
+    pub loopback: bool,
+    /// The interface supports multicast.
+    pub multicast: bool,
+    /// The interface is a point-to-point link.
+    pub point_to_point: bool,
+}
+
+/// A snapshot of an interface and its addresses, see `IfWatcher::interfaces`.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IfInterface {
+    /// Name, index, MTU, hardware address and type of the interface.
+    pub link: IfLink,
+    /// Flags of the interface. Only available on Linux.
+    pub flags: Option<IfFlags>,
+    /// Addresses assigned to the interface, sorted.
+    pub addrs: Vec<IpNet>,
+}
+
+/// An IP address family.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum IpFamily {
+    /// IPv4.
+    V4,
+    /// IPv6.
+    V6,
+}
+
+/// A change of the address used to reach the internet, see `PrimaryWatcher`.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PrimaryEvent {
+    /// Address family whose primary address has changed.
+    pub family: IpFamily,
+    /// The previous primary address, `None` if there was none.
+    pub old: Option<IfAddress>,
+    /// The new primary address, `None` if the internet is not reachable
+    /// through this family anymore.
+    pub new: Option<IfAddress>,
+}
+
+/// Connectivity of an address family, see `ConnectivityWatcher`.
+///
+/// The variants are ordered from least to most connected, so the overall
+/// connectivity is the maximum of both families.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Connectivity {
+    /// No usable address is assigned to an interface that is up.
+    #[default]
+    Offline,
+    /// Addresses are assigned but there is no default route, so only the
+    /// local networks are reachable.
+    Local,
+    /// Addresses are assigned and there is a default route.
+    Online,
+}
+
+/// A change of the connectivity of an address family.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConnectivityEvent {
+    /// Address family whose connectivity has changed.
+    pub family: IpFamily,
+    /// The previous connectivity.
+    pub old: Connectivity,
+    /// The new connectivity.
+    pub new: Connectivity,
+}
+
+/// A change of the prefixes of an interface, see `PrefixTracker`.
+///
+/// A prefix is the network of an address, e.g. `2001:db8:1::/64` for
+/// `2001:db8:1::2/64`. A prefix has been added if `old` is `None`, removed if
+/// `new` is `None` and renumbered if both are set.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PrefixEvent {
+    /// Index of the interface, if the platform reports one.
+    pub if_index: Option<u32>,
+    /// Name of the interface.
+    pub if_name: String,
+    /// The prefix that has been removed or replaced.
+    pub old: Option<IpNet>,
+    /// The prefix that has been added or replaced the old one.
+    pub new: Option<IpNet>,
+}
+
+/// Destination outside of the local networks whose route determines the
+/// primary IPv4 address, from TEST-NET-3 (RFC 5737).
+#[cfg(any(feature = "smol", feature = "tokio"))]
+const PROBE_V4: std::net::Ipv4Addr = std::net::Ipv4Addr::new(203, 0, 113, 1);
+/// Destination whose route determines the primary IPv6 address, from the
+/// documentation prefix (RFC 3849).
+#[cfg(any(feature = "smol", feature = "tokio"))]
+const PROBE_V6: std::net::Ipv6Addr = std::net::Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+
+/// Updates the primary address of `family` to the address whose IP is the
+/// source address `source` selected by the platform, returning an event if it
+/// changed.
+///
+/// Changes of the flags of the same address are not reported.
+#[cfg(any(feature = "smol", feature = "tokio"))]
+fn update_primary<'a>(
+    primary: &mut Option<IfAddress>,
+    family: IpFamily,
+    source: Option<std::net::IpAddr>,
+    mut addrs: impl Iterator<Item = &'a IfAddress>,
+) -> Option<PrimaryEvent> {
+    let new = source.and_then(|ip| addrs.find(|addr| addr.net.addr() == ip).cloned());
+    let changed = primary.as_ref().map(|addr| addr.net) != new.as_ref().map(|addr| addr.net);
+    let old = std::mem::replace(primary, new.clone());
+    changed.then_some(PrimaryEvent { family, old, new })
+}
+
+/// Whether `ip` is an IPv6 link-local unicast address from `fe80::/10`.
+fn is_link_local_v6(ip: &std::net::Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
+
+/// Classifies the connectivity of `family` from the addresses assigned to
+/// interfaces that are up and whether there is a usable default route.
+///
+/// Loopback and tentative addresses are not usable, and neither are IPv6
+/// link-local addresses since every IPv6 interface has one.
+#[cfg(any(feature = "smol", feature = "tokio"))]
+fn classify<'a>(
+    family: IpFamily,
+    mut addrs: impl Iterator<Item = &'a IfAddress>,
+    default_route: bool,
+) -> Connectivity {
+    let usable = addrs.any(|addr| {
+        let ip = addr.net.addr();
+        let link_local = match ip {
+            std::net::IpAddr::V4(_) => false,
+            std::net::IpAddr::V6(ip) => is_link_local_v6(&ip),
+        };
+        let tentative = addr.flags.is_some_and(|flags| flags.tentative);
+        ip.is_ipv4() == (family == IpFamily::V4) && !ip.is_loopback() && !link_local && !tentative
+    });
+    match (usable, default_route) {
+        (false, _) => Connectivity::Offline,
+        (true, false) => Connectivity::Local,
+        (true, true) => Connectivity::Online,
+    }
+}
+
+/// Guesses the kind of an interface from its name, for platforms that do not
+/// report it. Matching is case insensitive to cover the friendly names on
+/// Windows.
+#[cfg(not(target_os = "linux"))]
+#[cfg(any(feature = "smol", feature = "tokio"))]
+fn kind_from_name(name: &str, loopback: bool) -> Option<IfKind> {
+    const PREFIXES: &[(&str, IfKind)] = &[
+        ("wg", IfKind::WireGuard),
+        ("wireguard", IfKind::WireGuard),
+        ("utun", IfKind::Tun),
+        ("tun", IfKind::Tun),
+        ("tap", IfKind::Tap),
+        ("veth", IfKind::Veth),
+        ("br", IfKind::Bridge),
+        ("virbr", IfKind::Bridge),
+        ("docker", IfKind::Bridge),
+        ("bond", IfKind::Bond),
+        ("vlan", IfKind::Vlan),
+        ("wl", IfKind::Wlan),
+        ("wi-fi", IfKind::Wlan),
+        ("wireless", IfKind::Wlan),
+        ("awdl", IfKind::Wlan),
+        ("ppp", IfKind::Ppp),
+        ("gif", IfKind::Tunnel),
+        ("stf", IfKind::Tunnel),
+        ("gre", IfKind::Tunnel),
+        ("eth", IfKind::Ethernet),
+        ("en", IfKind::Ethernet),
+    ];
+    if loopback {
+        return Some(IfKind::Loopback);
+    }
+    let name = name.to_lowercase();
+    PREFIXES
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, kind)| kind.clone())
+}
+
+/// Groups `addrs` by the interface they are assigned to, matching them by index
+/// where the platform reports one and by name otherwise.
+#[cfg(any(feature = "smol", feature = "tokio"))]
+fn group_interfaces<'a>(
+    links: impl Iterator<Item = (&'a IfLink, Option<IfFlags>)>,
+    addrs: impl Iterator<Item = &'a IfAddress>,
+) -> Vec<IfInterface> {
+    let mut ifaces: Vec<_> = links
+        .map(|(link, flags)| IfInterface {
+            link: link.clone(),
+            flags,
+            addrs: Vec::new(),
+        })
+        .collect();
+    for addr in addrs {
+        let iface = ifaces.iter_mut().find(|iface| match addr.if_index {
+            Some(index) => iface.link.if_index == Some(index),
+            None => iface.link.if_name == addr.if_name,
+        });
+        if let Some(iface) = iface {
+            iface.addrs.push(addr.net);
+        }
+    }
+    for iface in &mut ifaces {
+        iface.addrs.sort();
+    }
+    ifaces.sort_by(|a, b| {
+        (a.link.if_index, &a.link.if_name).cmp(&(b.link.if_index, &b.link.if_name))
+    });
+    ifaces
+}
+
 #[cfg(test)]
 mod tests {
     use futures::StreamExt;
@@ -96,6 +611,66 @@ mod tests {
         println!("Got event {:?}", event);
     }
 
+    #[cfg(feature = "smol")]
+    #[test]
+    fn test_smol_link_watch() {
+        use super::smol::IfWatcher;
+        use super::IfLinkEvent;
+
+        smol::block_on(async {
+            let mut set = IfWatcher::new().unwrap();
+            let event = futures::future::poll_fn(|cx| set.poll_link_event(cx))
+                .await
+                .unwrap();
+            assert!(matches!(event, IfLinkEvent::Added(_)));
+            assert!(set.links().any(|link| link == event.link()));
+        });
+    }
+
+    #[cfg(feature = "smol")]
+    #[test]
+    fn test_smol_interfaces() {
+        use super::smol::IfWatcher;
+
+        smol::block_on(async {
+            let mut set = IfWatcher::new().unwrap();
+            futures::future::poll_fn(|cx| set.poll_synced(cx))
+                .await
+                .unwrap();
+            let ifaces = set.interfaces();
+            for addr in set.addresses() {
+                let iface = ifaces
+                    .iter()
+                    .find(|iface| iface.addrs.contains(&addr.net))
+                    .unwrap();
+                assert_eq!(iface.link.if_name, addr.if_name);
+            }
+        });
+    }
+
+    #[cfg(feature = "smol")]
+    #[test]
+    fn test_smol_current_addresses() {
+        use super::smol::{current_addresses, IfWatcher};
+
+        smol::block_on(async {
+            let mut set = IfWatcher::new().unwrap();
+            futures::future::poll_fn(|cx| set.poll_synced(cx))
+                .await
+                .unwrap();
+            let mut addrs: Vec<_> = set.addresses().cloned().collect();
+            addrs.sort();
+            assert_eq!(current_addresses().await.unwrap(), addrs);
+        });
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_tokio_current_addresses() {
+        let addrs = super::tokio::current_addresses().await.unwrap();
+        assert!(addrs.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
     #[test]
     fn test_smol_is_send() {
         use super::smol::IfWatcher;
@@ -108,6 +683,156 @@ mod tests {
         });
     }
 
+    #[cfg(feature = "smol")]
+    #[test]
+    fn test_smol_primary_watcher_is_send() {
+        use super::smol::PrimaryWatcher;
+
+        smol::block_on(async {
+            fn is_send<T: Send>(_: T) {}
+            let mut watcher = PrimaryWatcher::new().unwrap();
+            assert!(watcher.primary(super::IpFamily::V4).is_none());
+            let _ = futures::poll!(futures::future::poll_fn(|cx| watcher.poll_primary_event(cx)));
+            is_send(watcher);
+        });
+    }
+
+    #[cfg(feature = "smol")]
+    #[test]
+    fn test_smol_connectivity_watcher_is_send() {
+        use super::smol::ConnectivityWatcher;
+
+        smol::block_on(async {
+            fn is_send<T: Send>(_: T) {}
+            let mut watcher = ConnectivityWatcher::new().unwrap();
+            let _ = futures::poll!(futures::future::poll_fn(
+                |cx| watcher.poll_connectivity_event(cx)
+            ));
+            is_send(watcher);
+        });
+    }
+
+    #[cfg(any(feature = "smol", feature = "tokio"))]
+    #[test]
+    fn test_classify() {
+        use super::{classify, Connectivity, IfAddress, IfAddressFlags, IpFamily};
+
+        let addr = |net: &str, tentative| IfAddress {
+            flags: Some(IfAddressFlags {
+                tentative,
+                ..Default::default()
+            }),
+            ..IfAddress::test(net, 2, "eth0")
+        };
+        let cases = [
+            (IpFamily::V4, vec![], true, Connectivity::Offline),
+            (
+                IpFamily::V4,
+                vec![addr("127.0.0.1/8", false)],
+                true,
+                Connectivity::Offline,
+            ),
+            (
+                IpFamily::V4,
+                vec![addr("192.168.1.2/24", false)],
+                false,
+                Connectivity::Local,
+            ),
+            (
+                IpFamily::V4,
+                vec![addr("192.168.1.2/24", false)],
+                true,
+                Connectivity::Online,
+            ),
+            (
+                IpFamily::V6,
+                vec![addr("192.168.1.2/24", false)],
+                true,
+                Connectivity::Offline,
+            ),
+            (
+                IpFamily::V6,
+                vec![addr("fe80::1/64", false)],
+                false,
+                Connectivity::Offline,
+            ),
+            (
+                IpFamily::V6,
+                vec![addr("2001:db8::1/64", true)],
+                true,
+                Connectivity::Offline,
+            ),
+            (
+                IpFamily::V6,
+                vec![addr("fd00::1/64", false)],
+                false,
+                Connectivity::Local,
+            ),
+            (
+                IpFamily::V6,
+                vec![addr("fe80::1/64", false), addr("2001:db8::1/64", false)],
+                true,
+                Connectivity::Online,
+            ),
+        ];
+        for (family, addrs, default_route, expected) in cases {
+            assert_eq!(
+                classify(family, addrs.iter(), default_route),
+                expected,
+                "{:?}",
+                addrs
+            );
+        }
+    }
+
+    #[cfg(any(feature = "smol", feature = "tokio"))]
+    #[test]
+    fn test_update_primary() {
+        use super::{update_primary, IfAddress, IfAddressFlags, IpFamily, PrimaryEvent};
+
+        let addr =
+            |net: &str, if_index| IfAddress::test(net, if_index, &format!("eth{}", if_index));
+        let addrs = [
+            addr("192.168.1.2/24", 2),
+            addr("10.0.0.2/8", 3),
+            addr("2001:db8::2/64", 2),
+        ];
+        let v4 = IpFamily::V4;
+        let mut primary = None;
+        assert_eq!(update_primary(&mut primary, v4, None, addrs.iter()), None);
+        assert_eq!(
+            update_primary(&mut primary, v4, "10.0.0.2".parse().ok(), addrs.iter()),
+            Some(PrimaryEvent {
+                family: v4,
+                old: None,
+                new: Some(addrs[1].clone()),
+            })
+        );
+        assert_eq!(primary, Some(addrs[1].clone()));
+
+        // Only changes of the address are reported.
+        let mut flagged = addrs.clone();
+        flagged[1].flags = Some(IfAddressFlags::default());
+        let source = "10.0.0.2".parse().ok();
+        assert_eq!(
+            update_primary(&mut primary, v4, source, flagged.iter()),
+            None
+        );
+        assert_eq!(primary, Some(flagged[1].clone()));
+
+        // The source address is not assigned to an interface, e.g. because
+        // the address has been removed since.
+        let source = "172.16.0.1".parse().ok();
+        assert_eq!(
+            update_primary(&mut primary, v4, source, addrs.iter()),
+            Some(PrimaryEvent {
+                family: v4,
+                old: Some(flagged[1].clone()),
+                new: None,
+            })
+        );
+    }
+
     #[tokio::test]
     async fn test_tokio_is_send() {
         use super::tokio::IfWatcher;
@@ -117,4 +842,84 @@ mod tests {
         is_send(IfWatcher::new().unwrap());
         is_send(Pin::new(&mut IfWatcher::new().unwrap()));
     }
+
+    #[cfg(all(target_os = "linux", feature = "smol"))]
+    #[test]
+    fn test_smol_netns_watch() {
+        use super::smol::IfWatcher;
+
+        smol::block_on(async {
+            let mut set = match IfWatcher::new_in_netns("/proc/self/ns/net") {
+                // Entering a namespace requires `CAP_SYS_ADMIN`.
+                Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => return,
+                result => result.unwrap(),
+            };
+            assert_eq!(set.netns(), IfWatcher::new().unwrap().netns());
+            let event = set.select_next_some().await.unwrap();
+            println!("Got event {:?}", event);
+        });
+    }
+
+    #[cfg(all(target_os = "linux", feature = "smol"))]
+    #[test]
+    fn test_smol_neighbor_watch() {
+        use super::smol::NeighborWatcher;
+
+        smol::block_on(async {
+            let mut set = NeighborWatcher::new().unwrap();
+            futures::future::poll_fn(|cx| set.poll_synced(cx))
+                .await
+                .unwrap();
+            for neigh in set.neighbors() {
+                println!("Got neighbor {:?}", neigh);
+            }
+        });
+    }
+
+    #[cfg(all(target_os = "linux", feature = "smol"))]
+    #[test]
+    fn test_smol_netns_watcher_is_send() {
+        fn is_send<T: Send>(_: T) {}
+        is_send(super::smol::NetnsWatcher::new().unwrap());
+    }
+
+    #[cfg(all(target_os = "linux", feature = "smol"))]
+    #[test]
+    fn test_smol_wifi_watcher_is_send() {
+        fn is_send<T: Send>(_: T) {}
+        is_send(super::smol::WifiWatcher::new().unwrap());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_if_event() {
+        use super::IfEvent;
+
+        let event = IfEvent::Up("192.168.1.2/24".parse().unwrap());
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(json, r#"{"Up":"192.168.1.2/24"}"#);
+        assert_eq!(serde_json::from_str::<IfEvent>(&json).unwrap(), event);
+
+        let event = IfEvent::Down("fe80::1/64".parse().unwrap());
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(json, r#"{"Down":"fe80::1/64"}"#);
+        assert_eq!(serde_json::from_str::<IfEvent>(&json).unwrap(), event);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_if_address_event() {
+        use super::{IfAddress, IfAddressEvent};
+
+        let event = IfAddressEvent::Up(IfAddress::test("192.168.1.2/24", 2, "eth0"));
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Up":{"net":"192.168.1.2/24","if_index":2,"if_name":"eth0"}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<IfAddressEvent>(&json).unwrap(),
+            event
+        );
+    }
 }
@@ -1,27 +1,98 @@
-use crate::{IfEvent, IpNet, Ipv4Net, Ipv6Net};
-use fnv::FnvHashSet;
+use crate::filter::FilterState;
+use crate::{
+    IfAddress, IfAddressEvent, IfAddressFlags, IfEvent, IfFilter, IfFlags, IfInterface, IfKind,
+    IfLink, IfLinkEvent, IpNet, Ipv4Net, Ipv6Net, LinkType, StampedEvent,
+};
+use fnv::{FnvHashMap, FnvHashSet};
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::future::{self, FutureExt};
 use futures::ready;
-use futures::stream::{FusedStream, Stream, TryStreamExt};
+use futures::stream::{self, FusedStream, Stream, TryStreamExt};
 use futures::StreamExt;
-use rtnetlink::constants::{RTMGRP_IPV4_IFADDR, RTMGRP_IPV6_IFADDR};
+use rtnetlink::constants::{RTMGRP_IPV4_IFADDR, RTMGRP_IPV6_IFADDR, RTMGRP_LINK};
 use rtnetlink::packet::address::nlas::Nla;
-use rtnetlink::packet::{AddressMessage, RtnlMessage};
+use rtnetlink::packet::constants::{
+    ARPHRD_ETHER, ARPHRD_IPGRE, ARPHRD_LOOPBACK, ARPHRD_NONE, ARPHRD_PPP, ARPHRD_SIT,
+    ARPHRD_TUNNEL, ARPHRD_TUNNEL6, IFA_F_DEPRECATED, IFA_F_TEMPORARY, IFA_F_TENTATIVE,
+    IFF_LOOPBACK, IFF_MULTICAST, IFF_POINTOPOINT, IFF_RUNNING, IFF_UP,
+};
+use rtnetlink::packet::link::nlas::{Info, InfoKind, Nla as LinkNla};
+use rtnetlink::packet::NetlinkMessage;
+use rtnetlink::packet::{AddressMessage, LinkMessage, RtnlMessage};
 use rtnetlink::proto::{Connection, NetlinkPayload};
 use rtnetlink::sys::{AsyncSocket, SocketAddr};
+use rtnetlink::Handle;
+use std::collections::hash_map::Entry;
 use std::collections::VecDeque;
+use std::fs::File;
 use std::future::Future;
 use std::io::{Error, ErrorKind, Result};
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+mod connectivity;
+mod dns;
+mod inotify;
+mod mcast;
+mod neigh;
+mod netns;
+mod primary;
+mod raw;
+mod rule;
+mod wifi;
+
+/// Name of the backend in `tracing` events and metrics.
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+const BACKEND: &str = "linux";
+
 #[cfg(feature = "tokio")]
 pub mod tokio {
     //! An interface watcher that uses `rtnetlink`'s [`TokioSocket`](rtnetlink::sys::TokioSocket)
     use rtnetlink::sys::TokioSocket;
 
+    pub use super::dns::{DnsConfig, DnsWatcher};
+    pub use super::mcast::{MulticastEvent, MulticastMembership};
+    pub use super::neigh::{Neighbor, NeighborEvent, NudState};
+    pub use super::netns::NetnsEvent;
+    pub use super::rule::{Rule, RuleAction, RuleEvent};
+    pub use super::wifi::{WifiEvent, WifiLink};
+
+    /// Watches the connectivity per address family.
+    pub type ConnectivityWatcher = super::connectivity::ConnectivityWatcher<TokioSocket>;
+
     /// Watches for interface changes.
     pub type IfWatcher = super::IfWatcher<TokioSocket>;
+
+    /// Returns the current addresses, sorted.
+    ///
+    /// The addresses are loaded like the initial addresses of an [`IfWatcher`],
+    /// so they match the addresses of its events.
+    pub async fn current_addresses() -> std::io::Result<Vec<crate::IfAddress>> {
+        super::current_addresses::<TokioSocket>().await
+    }
+
+    /// Watches the multicast capability of interfaces and the multicast
+    /// groups joined on them.
+    pub type MulticastWatcher = super::mcast::MulticastWatcher<TokioSocket>;
+
+    /// Watches the neighbor (ARP and NDP) table.
+    pub type NeighborWatcher = super::neigh::NeighborWatcher<TokioSocket>;
+
+    /// Watches for interface changes in all named network namespaces.
+    pub type NetnsWatcher = super::netns::NetnsWatcher<TokioSocket>;
+
+    /// Watches the address used to reach the internet.
+    pub type PrimaryWatcher = super::primary::PrimaryWatcher<TokioSocket>;
+
+    /// Watches the routing policy rules.
+    pub type RuleWatcher = super::rule::RuleWatcher<TokioSocket>;
+
+    /// Watches the associations of wireless interfaces.
+    pub type WifiWatcher = super::wifi::WifiWatcher<TokioSocket>;
 }
 
 #[cfg(feature = "smol")]
@@ -29,15 +100,97 @@ pub mod smol {
     //! An interface watcher that uses `rtnetlink`'s [`SmolSocket`](rtnetlink::sys::SmolSocket)
     use rtnetlink::sys::SmolSocket;
 
+    pub use super::dns::{DnsConfig, DnsWatcher};
+    pub use super::mcast::{MulticastEvent, MulticastMembership};
+    pub use super::neigh::{Neighbor, NeighborEvent, NudState};
+    pub use super::netns::NetnsEvent;
+    pub use super::rule::{Rule, RuleAction, RuleEvent};
+    pub use super::wifi::{WifiEvent, WifiLink};
+
+    /// Watches the connectivity per address family.
+    pub type ConnectivityWatcher = super::connectivity::ConnectivityWatcher<SmolSocket>;
+
     /// Watches for interface changes.
     pub type IfWatcher = super::IfWatcher<SmolSocket>;
+
+    /// Returns the current addresses, sorted.
+    ///
+    /// The addresses are loaded like the initial addresses of an [`IfWatcher`],
+    /// so they match the addresses of its events.
+    pub async fn current_addresses() -> std::io::Result<Vec<crate::IfAddress>> {
+        super::current_addresses::<SmolSocket>().await
+    }
+
+    /// Watches the multicast capability of interfaces and the multicast
+    /// groups joined on them.
+    pub type MulticastWatcher = super::mcast::MulticastWatcher<SmolSocket>;
+
+    /// Watches the neighbor (ARP and NDP) table.
+    pub type NeighborWatcher = super::neigh::NeighborWatcher<SmolSocket>;
+
+    /// Watches for interface changes in all named network namespaces.
+    pub type NetnsWatcher = super::netns::NetnsWatcher<SmolSocket>;
+
+    /// Watches the address used to reach the internet.
+    pub type PrimaryWatcher = super::primary::PrimaryWatcher<SmolSocket>;
+
+    /// Watches the routing policy rules.
+    pub type RuleWatcher = super::rule::RuleWatcher<SmolSocket>;
+
+    /// Watches the associations of wireless interfaces.
+    pub type WifiWatcher = super::wifi::WifiWatcher<SmolSocket>;
+}
+
+/// Messages of the initial dump followed by `None` once the dump is complete,
+/// followed by notifications, with the sequence number of their netlink
+/// header. The sequence number of dumped messages is unknown and `0`.
+type MessageStream = Pin<Box<dyn Stream<Item = Result<Option<(RtnlMessage, u32)>>> + Send>>;
+
+/// Messages of a dump.
+type DumpStream = Pin<Box<dyn Stream<Item = Result<RtnlMessage>> + Send>>;
+
+/// Notifications with the sequence number of their netlink header.
+type NotificationStream = Pin<Box<dyn Stream<Item = Result<(RtnlMessage, u32)>> + Send>>;
+
+/// A dump of the links and addresses after an overrun.
+#[derive(Debug)]
+struct Resync {
+    #[cfg(feature = "metrics")]
+    started: std::time::Instant,
+    /// Links and addresses not yet seen in the dump.
+    links: FnvHashSet<u32>,
+    addrs: FnvHashSet<IpNet>,
+    /// Number of addresses added by the dump.
+    #[cfg(feature = "tracing")]
+    up: usize,
 }
 
 pub struct IfWatcher<T> {
     conn: Connection<RtnlMessage, T>,
-    messages: Pin<Box<dyn Stream<Item = Result<RtnlMessage>> + Send>>,
-    addrs: FnvHashSet<IpNet>,
-    queue: VecDeque<IfEvent>,
+    handle: Handle,
+    /// The dump in progress, whose messages are received before
+    /// notifications.
+    dump: Option<DumpStream>,
+    notifications: NotificationStream,
+    /// Number of notifications the kernel has dropped because the receive
+    /// buffer of the socket overflowed.
+    drops: u32,
+    /// Whether notifications have been dropped and a dump has to be requested
+    /// once the received notifications have been handled.
+    overrun: bool,
+    resync: Option<Resync>,
+    links: FnvHashMap<u32, IfLink>,
+    link_flags: FnvHashMap<u32, IfFlags>,
+    addrs: FnvHashMap<IpNet, IfAddress>,
+    queue: VecDeque<StampedEvent>,
+    seq: u64,
+    link_queue: VecDeque<IfLinkEvent>,
+    link_events: bool,
+    filter: FilterState,
+    synced: bool,
+    netns: Option<u64>,
+    /// Whether `/sys/class/net` describes the watched namespace.
+    sysfs: bool,
 }
 
 impl<T> std::fmt::Debug for IfWatcher<T> {
@@ -54,72 +207,643 @@ where
 {
     /// Create a watcher.
     pub fn new() -> Result<Self> {
-        let (mut conn, handle, messages) = rtnetlink::new_connection_with_socket::<T>()?;
-        let groups = RTMGRP_IPV4_IFADDR | RTMGRP_IPV6_IFADDR;
+        let (conn, handle, messages) = rtnetlink::new_connection_with_socket::<T>()?;
+        let netns = File::open("/proc/thread-self/ns/net")
+            .and_then(|ns| ns.metadata())
+            .map(|metadata| metadata.ino())
+            .ok();
+        Self::from_connection(conn, handle, messages, netns, true)
+    }
+
+    fn from_connection(
+        mut conn: Connection<RtnlMessage, T>,
+        handle: Handle,
+        messages: UnboundedReceiver<(NetlinkMessage<RtnlMessage>, SocketAddr)>,
+        netns: Option<u64>,
+        sysfs: bool,
+    ) -> Result<Self> {
+        let groups = RTMGRP_LINK | RTMGRP_IPV4_IFADDR | RTMGRP_IPV6_IFADDR;
         let addr = SocketAddr::new(0, groups);
-        conn.socket_mut().socket_mut().bind(&addr)?;
-        let get_addrs_stream = handle
-            .address()
-            .get()
-            .execute()
-            .map_ok(RtnlMessage::NewAddress)
-            .map_err(|err| Error::new(ErrorKind::Other, err));
-        let msg_stream = messages.filter_map(|(msg, _)| async {
-            match msg.payload {
-                NetlinkPayload::Error(err) => Some(Err(err.to_io())),
-                NetlinkPayload::InnerMessage(msg) => Some(Ok(msg)),
-                _ => None,
-            }
-        });
-        let messages = get_addrs_stream.chain(msg_stream).boxed();
-        let addrs = FnvHashSet::default();
-        let queue = VecDeque::default();
+        let socket = conn.socket_mut().socket_mut();
+        socket.bind(&addr)?;
+        // Overruns are detected with the drop counter of the socket instead of
+        // failing reads, which close the connection.
+        socket.set_no_enobufs(true)?;
+        let drops = socket_drops(socket)?;
+        let dump = dump_stream(&handle);
+        #[cfg(feature = "tracing")]
+        crate::trace::setup(BACKEND, netns);
         Ok(Self {
             conn,
-            messages,
-            addrs,
-            queue,
+            handle,
+            dump: Some(dump),
+            notifications: notification_stream(messages),
+            drops,
+            overrun: false,
+            resync: None,
+            links: Default::default(),
+            link_flags: Default::default(),
+            addrs: Default::default(),
+            queue: Default::default(),
+            seq: 0,
+            link_queue: Default::default(),
+            link_events: false,
+            filter: Default::default(),
+            synced: false,
+            netns,
+            sysfs,
         })
     }
 
+    /// Inode number of the network namespace being watched.
+    pub fn netns(&self) -> Option<u64> {
+        self.netns
+    }
+
     /// Iterate over current networks.
     pub fn iter(&self) -> impl Iterator<Item = &IpNet> {
-        self.addrs.iter()
+        self.addresses().map(|addr| &addr.net)
     }
 
-    fn add_address(&mut self, msg: AddressMessage) {
-        for net in iter_nets(msg) {
-            if self.addrs.insert(net) {
-                self.queue.push_back(IfEvent::Up(net));
+    /// Iterate over current addresses and their interfaces.
+    pub fn addresses(&self) -> impl Iterator<Item = &IfAddress> {
+        self.addrs
+            .values()
+            .filter(|addr| self.filter.contains(addr))
+    }
+
+    /// Exclude addresses from the events and iterators of the watcher based on
+    /// their interface. Events are filtered when they are queued, and `Up` and
+    /// `Down` events are queued for the current addresses whose inclusion
+    /// changed, so every `Down` event follows an `Up` event of its address.
+    pub fn set_filter(&mut self, filter: IfFilter) {
+        let links = &self.links;
+        let addrs = self.addrs.values().map(|addr| {
+            let link = addr.if_index.and_then(|index| links.get(&index));
+            (addr, link)
+        });
+        let events = self.filter.set_filter(filter, addrs);
+        self.queue.extend(events);
+    }
+
+    /// Iterate over current interfaces.
+    pub fn links(&self) -> impl Iterator<Item = &IfLink> {
+        self.links.values()
+    }
+
+    /// Flags of the interface with index `if_index`, if it is known.
+    pub fn link_flags(&self, if_index: u32) -> Option<IfFlags> {
+        self.link_flags.get(&if_index).copied()
+    }
+
+    /// Select the best current address for sending to `dst`, see
+    /// [`select_source`](crate::select_source).
+    pub fn select_source(&self, dst: IpAddr) -> Option<&IfAddress> {
+        crate::select_source(dst, self.addresses())
+    }
+
+    /// Snapshot of the current interfaces and their addresses.
+    pub fn interfaces(&self) -> Vec<IfInterface> {
+        let links = self
+            .links
+            .iter()
+            .map(|(index, link)| (link, self.link_flags.get(index).copied()));
+        crate::group_interfaces(links, self.addresses())
+    }
+
+    fn add_link(&mut self, msg: LinkMessage) {
+        let index = msg.header.index;
+        let flags = msg.header.flags;
+        self.link_flags.insert(
+            index,
+            IfFlags {
+                up: flags & IFF_UP != 0,
+                running: flags & IFF_RUNNING != 0,
+                loopback: flags & IFF_LOOPBACK != 0,
+                multicast: flags & IFF_MULTICAST != 0,
+                point_to_point: flags & IFF_POINTOPOINT != 0,
+            },
+        );
+        let link = parse_link(msg, self.sysfs);
+        if let Some(resync) = &mut self.resync {
+            resync.links.remove(&index);
+        }
+        let event = match self.links.entry(index) {
+            Entry::Vacant(entry) => IfLinkEvent::Added(entry.insert(link).clone()),
+            // The kernel also notifies about changes of other attributes, such
+            // as the operational state or statistics.
+            Entry::Occupied(entry) if *entry.get() == link => return,
+            Entry::Occupied(mut entry) => IfLinkEvent::Changed {
+                old: entry.insert(link.clone()),
+                new: link,
+            },
+        };
+        if self.link_events {
+            self.link_queue.push_back(event);
+        }
+    }
+
+    fn rem_link(&mut self, index: u32) {
+        self.link_flags.remove(&index);
+        if let Some(link) = self.links.remove(&index) {
+            if self.link_events {
+                self.link_queue.push_back(IfLinkEvent::Removed(link));
             }
         }
     }
 
-    fn rem_address(&mut self, msg: AddressMessage) {
+    fn add_address(&mut self, msg: AddressMessage, seq: u32) {
+        let index = msg.header.index;
+        let label = msg.nlas.iter().find_map(|nla| match nla {
+            Nla::Label(label) => Some(label.clone()),
+            _ => None,
+        });
+        let name = self
+            .links
+            .get(&index)
+            .map(|link| link.if_name.clone())
+            .or(label);
+        // The extended flags attribute supersedes the flags in the header.
+        let flags = msg
+            .nlas
+            .iter()
+            .find_map(|nla| match nla {
+                Nla::Flags(flags) => Some(*flags),
+                _ => None,
+            })
+            .unwrap_or(msg.header.flags.into());
+        let flags = IfAddressFlags {
+            deprecated: flags & IFA_F_DEPRECATED != 0,
+            temporary: flags & IFA_F_TEMPORARY != 0,
+            tentative: flags & IFA_F_TENTATIVE != 0,
+        };
+        let kernel_seq = (seq != 0).then_some(seq);
         for net in iter_nets(msg) {
-            if self.addrs.remove(&net) {
-                self.queue.push_back(IfEvent::Down(net));
+            if let Some(resync) = &mut self.resync {
+                resync.addrs.remove(&net);
+            }
+            match self.addrs.entry(net) {
+                Entry::Vacant(entry) => {
+                    #[cfg(feature = "tracing")]
+                    if let Some(resync) = &mut self.resync {
+                        resync.up += 1;
+                    }
+                    let addr = entry.insert(IfAddress {
+                        net,
+                        if_index: Some(index),
+                        if_name: name.clone().unwrap_or_default(),
+                        netns: self.netns,
+                        flags: Some(flags),
+                    });
+                    let event = IfAddressEvent::Up(addr.clone());
+                    let link = self.links.get(&index);
+                    let stamped = self.filter.stamp(event, link, kernel_seq);
+                    self.queue.push_back(stamped);
+                }
+                // The kernel also notifies about refreshed lifetimes.
+                Entry::Occupied(entry) if entry.get().flags == Some(flags) => {}
+                // Flags change when the address becomes deprecated or duplicate
+                // address detection completes.
+                Entry::Occupied(mut entry) => {
+                    let old = entry.get().clone();
+                    entry.get_mut().flags = Some(flags);
+                    let event = IfAddressEvent::Changed {
+                        old,
+                        new: entry.get().clone(),
+                    };
+                    let stamped = self.filter.stamp(event, None, kernel_seq);
+                    self.queue.push_back(stamped);
+                }
             }
         }
     }
 
-    /// Poll for an address change event.
-    pub fn poll_if_event(&mut self, cx: &mut Context) -> Poll<Result<IfEvent>> {
+    fn rem_address(&mut self, net: IpNet, kernel_seq: Option<u32>) {
+        if let Some(addr) = self.addrs.remove(&net) {
+            let event = IfAddressEvent::Down(addr);
+            let stamped = self.filter.stamp(event, None, kernel_seq);
+            self.queue.push_back(stamped);
+        }
+    }
+
+    /// Whether the kernel has dropped notifications since the last call.
+    fn dropped(&mut self) -> Result<bool> {
+        let drops = socket_drops(self.conn.socket_mut().socket_mut())?;
+        let dropped = drops != self.drops;
+        self.drops = drops;
+        Ok(dropped)
+    }
+
+    /// Dumps the links and addresses again to recover from an overrun, the
+    /// ones missing from the dump are removed once it is complete.
+    fn start_resync(&mut self) {
+        self.overrun = false;
+        self.resync = Some(Resync {
+            #[cfg(feature = "metrics")]
+            started: std::time::Instant::now(),
+            links: self.links.keys().copied().collect(),
+            addrs: self.addrs.keys().copied().collect(),
+            #[cfg(feature = "tracing")]
+            up: 0,
+        });
+        self.dump = Some(dump_stream(&self.handle));
+    }
+
+    fn finish_resync(&mut self, resync: Resync) {
+        // Notifications are not handled during the dump, so the addresses
+        // missing from it are still known.
+        #[cfg(feature = "tracing")]
+        crate::trace::resync(BACKEND, resync.up, resync.addrs.len());
+        for net in resync.addrs {
+            self.rem_address(net, None);
+        }
+        for index in resync.links {
+            self.rem_link(index);
+        }
+        #[cfg(feature = "metrics")]
+        {
+            crate::stats::resync(BACKEND, resync.started.elapsed());
+            crate::stats::queue(BACKEND, self.queue.len());
+        }
+    }
+
+    fn recv_message(&mut self, cx: &mut Context) -> Poll<Result<Option<(RtnlMessage, u32)>>> {
+        // The connection is closed when reading from the socket fails.
+        if Pin::new(&mut self.conn).poll(cx).is_ready() {
+            return Poll::Ready(Err(socket_err()));
+        }
+        if self.dropped()? {
+            #[cfg(feature = "metrics")]
+            crate::stats::recv_error(BACKEND, &Error::from_raw_os_error(libc::ENOBUFS));
+            self.overrun = true;
+        }
+        loop {
+            if let Some(dump) = &mut self.dump {
+                let message = ready!(dump.poll_next_unpin(cx));
+                if message.is_none() {
+                    self.dump = None;
+                }
+                return Poll::Ready(message.transpose().map(|msg| msg.map(|msg| (msg, 0))));
+            }
+            match self.notifications.poll_next_unpin(cx) {
+                Poll::Ready(Some(message)) => return Poll::Ready(message.map(Some)),
+                Poll::Ready(None) => return Poll::Ready(Err(socket_err())),
+                // The dump is requested once the notifications received before
+                // it have been handled, as they may be older than the dump.
+                Poll::Pending if self.overrun => self.start_resync(),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_message(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        let message = ready!(self.recv_message(cx));
+        #[cfg(feature = "metrics")]
+        if let Err(err) = &message {
+            crate::stats::error(BACKEND, err);
+        }
+        let message = message?;
+        // Messages of the initial dump are not notifications.
+        #[cfg(feature = "tracing")]
+        if let Some((msg, seq)) = message.as_ref().filter(|_| self.synced) {
+            let kind = match msg {
+                RtnlMessage::NewLink(_) => "new_link",
+                RtnlMessage::DelLink(_) => "del_link",
+                RtnlMessage::NewAddress(_) => "new_address",
+                RtnlMessage::DelAddress(_) => "del_address",
+                _ => "other",
+            };
+            crate::trace::notification(BACKEND, kind, (*seq != 0).then_some(*seq));
+        }
+        match message {
+            Some((RtnlMessage::NewLink(msg), _)) => self.add_link(msg),
+            Some((RtnlMessage::DelLink(msg), _)) => self.rem_link(msg.header.index),
+            Some((RtnlMessage::NewAddress(msg), seq)) => self.add_address(msg, seq),
+            Some((RtnlMessage::DelAddress(msg), seq)) => {
+                let kernel_seq = (seq != 0).then_some(seq);
+                for net in iter_nets(msg) {
+                    self.rem_address(net, kernel_seq);
+                }
+            }
+            Some(_) => {}
+            None if self.resync.is_some() => {
+                if let Some(resync) = self.resync.take() {
+                    self.finish_resync(resync);
+                }
+            }
+            None => {
+                self.synced = true;
+                #[cfg(feature = "tracing")]
+                crate::trace::synced(BACKEND, self.addrs.len());
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Poll until the addresses present when the watcher was created have been
+    /// loaded. Events for these addresses remain queued.
+    pub fn poll_synced(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        while !self.synced {
+            ready!(self.poll_message(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Poll for an address change event including the interface of the
+    /// address.
+    pub fn poll_address_event(&mut self, cx: &mut Context) -> Poll<Result<IfAddressEvent>> {
+        self.poll_stamped_event(cx).map_ok(|stamped| stamped.event)
+    }
+
+    /// Poll for an address change event with the time it was received, its
+    /// sequence number and the sequence number of the netlink request that
+    /// caused it.
+    pub fn poll_stamped_event(&mut self, cx: &mut Context) -> Poll<Result<StampedEvent>> {
+        loop {
+            if let Some(mut stamped) = self.queue.pop_front() {
+                if stamped.included {
+                    stamped.seq = self.seq;
+                    self.seq += 1;
+                    #[cfg(feature = "tracing")]
+                    crate::trace::event(BACKEND, &stamped);
+                    #[cfg(feature = "metrics")]
+                    {
+                        crate::stats::event(BACKEND, &stamped);
+                        crate::stats::queue(BACKEND, self.queue.len());
+                    }
+                    return Poll::Ready(Ok(stamped));
+                }
+                continue;
+            }
+            #[cfg(feature = "metrics")]
+            crate::stats::queue(BACKEND, 0);
+            ready!(self.poll_message(cx))?;
+        }
+    }
+
+    /// Poll for a link change event.
+    ///
+    /// Link events are only recorded once this method has been called, so
+    /// that watchers only interested in addresses do not accumulate them. The
+    /// first call queues an `Added` event for every current interface.
+    pub fn poll_link_event(&mut self, cx: &mut Context) -> Poll<Result<IfLinkEvent>> {
+        if !self.link_events {
+            self.link_events = true;
+            let links = self.links.values().cloned().map(IfLinkEvent::Added);
+            self.link_queue.extend(links);
+        }
         loop {
-            if let Some(event) = self.queue.pop_front() {
+            if let Some(event) = self.link_queue.pop_front() {
                 return Poll::Ready(Ok(event));
             }
-            if Pin::new(&mut self.conn).poll(cx).is_ready() {
-                return Poll::Ready(Err(socket_err()));
+            ready!(self.poll_message(cx))?;
+        }
+    }
+
+    /// Poll for an address change event. Changes of the flags of an address
+    /// are skipped.
+    pub fn poll_if_event(&mut self, cx: &mut Context) -> Poll<Result<IfEvent>> {
+        loop {
+            let event = ready!(self.poll_address_event(cx))?;
+            if let Some(event) = event.to_if_event() {
+                return Poll::Ready(Ok(event));
             }
-            let message = ready!(self.messages.poll_next_unpin(cx)).ok_or_else(socket_err)??;
-            match message {
-                RtnlMessage::NewAddress(msg) => self.add_address(msg),
-                RtnlMessage::DelAddress(msg) => self.rem_address(msg),
-                _ => {}
+        }
+    }
+}
+
+impl<T> IfWatcher<T>
+where
+    T: AsyncSocket + FromRawFd + Unpin,
+{
+    /// Create a watcher for the network namespace at `path`, e.g.
+    /// `/var/run/netns/NAME` or `/proc/PID/ns/net`.
+    pub fn new_in_netns<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new_in_netns_fd(File::open(path)?.as_fd())
+    }
+
+    /// Create a watcher for the network namespace referred to by `fd`.
+    ///
+    /// The netlink socket is opened on a helper thread that enters the
+    /// namespace with `setns`, which requires `CAP_SYS_ADMIN`. The namespace of
+    /// the calling thread is not changed.
+    pub fn new_in_netns_fd(fd: BorrowedFd) -> Result<Self> {
+        let ns = File::from(fd.try_clone_to_owned()?);
+        let netns = ns.metadata()?.ino();
+        let socket = std::thread::spawn(move || open_socket_in_netns(&ns))
+            .join()
+            .map_err(|_| Error::other("netns thread panicked"))??;
+        Self::from_netns_socket(socket, netns)
+    }
+
+    /// Create a watcher for the network namespace with the inode number
+    /// `netns` from a netlink socket opened in it.
+    fn from_netns_socket(socket: OwnedFd, netns: u64) -> Result<Self> {
+        let (mut conn, handle, messages) = rtnetlink::new_connection_with_socket::<T>()?;
+        // Replace the socket opened in the current namespace by the one opened
+        // in the target namespace.
+        *conn.socket_mut() = unsafe { T::from_raw_fd(socket.into_raw_fd()) };
+        Self::from_connection(conn, handle, messages, Some(netns), false)
+    }
+}
+
+fn open_socket_in_netns(ns: &File) -> Result<OwnedFd> {
+    if unsafe { libc::setns(ns.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        )
+    };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+async fn current_addresses<T>() -> Result<Vec<IfAddress>>
+where
+    T: AsyncSocket + Unpin,
+{
+    let mut watcher = IfWatcher::<T>::new()?;
+    future::poll_fn(|cx| watcher.poll_synced(cx)).await?;
+    let mut addrs: Vec<_> = watcher.addresses().cloned().collect();
+    addrs.sort();
+    Ok(addrs)
+}
+
+/// Parses the properties of a link. The `wireless` directory in `sysfs` is
+/// only consulted if it describes the namespace of the link.
+fn parse_link(msg: LinkMessage, sysfs: bool) -> IfLink {
+    let link_layer_type = msg.header.link_layer_type;
+    let mut link = IfLink {
+        if_index: Some(msg.header.index),
+        if_name: String::new(),
+        mtu: None,
+        mac: None,
+        link_type: Some(match link_layer_type {
+            ARPHRD_ETHER => LinkType::Ethernet,
+            ARPHRD_LOOPBACK => LinkType::Loopback,
+            ARPHRD_PPP => LinkType::Ppp,
+            ARPHRD_TUNNEL | ARPHRD_TUNNEL6 | ARPHRD_SIT | ARPHRD_IPGRE => LinkType::Tunnel,
+            ARPHRD_NONE => LinkType::RawIp,
+            other => LinkType::Other(other),
+        }),
+        kind: None,
+    };
+    let mut info_kind = None;
+    for nla in msg.nlas {
+        match nla {
+            LinkNla::IfName(name) => link.if_name = name,
+            LinkNla::Mtu(mtu) => link.mtu = Some(mtu),
+            LinkNla::Address(mac) => link.mac = Some(mac),
+            LinkNla::Info(infos) => {
+                info_kind = infos.into_iter().find_map(|info| match info {
+                    Info::Kind(kind) => Some(kind),
+                    _ => None,
+                })
             }
+            _ => {}
         }
     }
+    link.kind = match info_kind {
+        Some(kind) => Some(parse_kind(kind, link_layer_type)),
+        None => match link.link_type {
+            Some(LinkType::Loopback) => Some(IfKind::Loopback),
+            Some(LinkType::Ppp) => Some(IfKind::Ppp),
+            Some(LinkType::Tunnel) => Some(IfKind::Tunnel),
+            Some(LinkType::Ethernet) if sysfs && is_wireless(&link.if_name) => Some(IfKind::Wlan),
+            Some(LinkType::Ethernet) => Some(IfKind::Ethernet),
+            _ => None,
+        },
+    };
+    link
+}
+
+/// Maps the `IFLA_INFO_KIND` of a virtual link to an [`IfKind`]. `tun` devices
+/// in tap mode have an Ethernet hardware type.
+fn parse_kind(kind: InfoKind, link_layer_type: u16) -> IfKind {
+    match kind {
+        InfoKind::Bridge => IfKind::Bridge,
+        InfoKind::Veth => IfKind::Veth,
+        InfoKind::Vlan => IfKind::Vlan,
+        InfoKind::Bond => IfKind::Bond,
+        InfoKind::MacVlan => IfKind::Macvlan,
+        InfoKind::Wireguard => IfKind::WireGuard,
+        InfoKind::Tun if link_layer_type == ARPHRD_ETHER => IfKind::Tap,
+        InfoKind::Tun => IfKind::Tun,
+        InfoKind::Vxlan
+        | InfoKind::GreTap
+        | InfoKind::GreTap6
+        | InfoKind::IpTun
+        | InfoKind::SitTun
+        | InfoKind::GreTun
+        | InfoKind::GreTun6
+        | InfoKind::Vti => IfKind::Tunnel,
+        InfoKind::Dummy => IfKind::Other("dummy".into()),
+        InfoKind::Ifb => IfKind::Other("ifb".into()),
+        InfoKind::Nlmon => IfKind::Other("nlmon".into()),
+        InfoKind::IpVlan => IfKind::Other("ipvlan".into()),
+        InfoKind::MacVtap => IfKind::Other("macvtap".into()),
+        InfoKind::Vrf => IfKind::Other("vrf".into()),
+        InfoKind::Gtp => IfKind::Other("gtp".into()),
+        InfoKind::Ipoib => IfKind::Other("ipoib".into()),
+        InfoKind::Other(kind) => IfKind::Other(kind),
+    }
+}
+
+/// Whether the interface `name` is a wireless LAN interface according to
+/// `sysfs`.
+fn is_wireless(name: &str) -> bool {
+    let path = Path::new("/sys/class/net").join(name);
+    path.join("wireless").exists() || path.join("phy80211").exists()
+}
+
+/// Chains the messages of the initial `dump`, a `None` marking its end and the
+/// notifications received in `messages`.
+fn message_stream(
+    dump: DumpStream,
+    messages: UnboundedReceiver<(NetlinkMessage<RtnlMessage>, SocketAddr)>,
+) -> MessageStream {
+    let dump_stream = dump
+        .map_ok(|msg| Some((msg, 0)))
+        .chain(stream::once(async { Ok(None) }));
+    dump_stream
+        .chain(notification_stream(messages).map_ok(Some))
+        .boxed()
+}
+
+fn notification_stream(
+    messages: UnboundedReceiver<(NetlinkMessage<RtnlMessage>, SocketAddr)>,
+) -> NotificationStream {
+    messages
+        .filter_map(|(msg, _)| async move {
+            let seq = msg.header.sequence_number;
+            match msg.payload {
+                NetlinkPayload::Error(err) => Some(Err(err.to_io())),
+                NetlinkPayload::InnerMessage(msg) => Some(Ok((msg, seq))),
+                _ => None,
+            }
+        })
+        .boxed()
+}
+
+/// Dumps the links followed by the addresses.
+fn dump_stream(handle: &Handle) -> DumpStream {
+    let get_links_stream = handle.link().get().execute().map_ok(RtnlMessage::NewLink);
+    let handle = handle.clone();
+    sequential_dump(get_links_stream, move || {
+        handle
+            .address()
+            .get()
+            .execute()
+            .map_ok(RtnlMessage::NewAddress)
+    })
+}
+
+/// Chains the dump `first` with the dump requested by `second`.
+///
+/// Only one dump can be in progress per socket, so the second dump is only
+/// requested once the first one has completed.
+fn sequential_dump<A, B, F>(first: A, second: F) -> DumpStream
+where
+    A: Stream<Item = std::result::Result<RtnlMessage, rtnetlink::Error>> + Send + 'static,
+    B: Stream<Item = std::result::Result<RtnlMessage, rtnetlink::Error>> + Send + 'static,
+    F: FnOnce() -> B + Send + 'static,
+{
+    let second = future::lazy(move |_| second()).flatten_stream();
+    first.chain(second).map_err(Error::other).boxed()
+}
+
+fn parse_ip(family: u8, octets: &[u8]) -> Option<IpAddr> {
+    match family {
+        2 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(octets).ok()?).into()),
+        10 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(octets).ok()?).into()),
+        _ => None,
+    }
+}
+
+/// Number of packets dropped by a socket, which for netlink sockets are
+/// notifications that did not fit into the receive buffer.
+fn socket_drops(socket: &impl AsRawFd) -> Result<u32> {
+    let mut meminfo = [0u32; libc::SK_MEMINFO_DROPS as usize + 1];
+    let mut len = std::mem::size_of_val(&meminfo) as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_MEMINFO,
+            meminfo.as_mut_ptr().cast(),
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(meminfo[libc::SK_MEMINFO_DROPS as usize])
 }
 
 fn socket_err() -> std::io::Error {
@@ -172,3 +896,51 @@ where
         false
     }
 }
+
+impl<T> crate::AddressWatcher for IfWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    fn poll_address_event(&mut self, cx: &mut Context) -> Poll<Result<IfAddressEvent>> {
+        IfWatcher::poll_address_event(self, cx)
+    }
+}
+
+#[cfg(all(test, feature = "smol"))]
+mod tests {
+    use super::IfWatcher;
+    use crate::IfAddressEvent;
+    use futures::future::poll_fn;
+    use rtnetlink::packet::address::nlas::Nla;
+    use rtnetlink::packet::AddressMessage;
+    use rtnetlink::sys::SmolSocket;
+
+    #[test]
+    fn test_overrun_resync() {
+        smol::block_on(async {
+            let mut watcher = IfWatcher::<SmolSocket>::new().unwrap();
+            poll_fn(|cx| watcher.poll_synced(cx)).await.unwrap();
+            watcher.queue.clear();
+            // An address whose removal was dropped.
+            let mut msg = AddressMessage::default();
+            msg.header.family = 2;
+            msg.header.prefix_len = 32;
+            msg.header.index = u32::MAX;
+            msg.nlas = vec![Nla::Address(vec![192, 0, 2, 1])];
+            watcher.add_address(msg, 0);
+            let event = poll_fn(|cx| watcher.poll_address_event(cx)).await;
+            assert!(matches!(event, Ok(IfAddressEvent::Up(_))));
+            // After an overrun the address is missing from the dump.
+            watcher.drops = watcher.drops.wrapping_sub(1);
+            let event = poll_fn(|cx| watcher.poll_address_event(cx)).await;
+            match event {
+                Ok(IfAddressEvent::Down(addr)) => {
+                    assert_eq!(addr.net, "192.0.2.1/32".parse().unwrap())
+                }
+                event => panic!("unexpected event {:?}", event),
+            }
+            assert!(watcher.resync.is_none());
+            assert!(!watcher.dropped().unwrap());
+        });
+    }
+}
@@ -0,0 +1,213 @@
+//! Classification of the connectivity from addresses, links and routes.
+use super::{message_stream, sequential_dump, socket_err, IfWatcher, MessageStream};
+use crate::{Connectivity, ConnectivityEvent, IpFamily};
+use fnv::FnvHashSet;
+use futures::stream::{FusedStream, Stream, TryStreamExt};
+use futures::StreamExt;
+use rtnetlink::constants::{RTMGRP_IPV4_ROUTE, RTMGRP_IPV6_ROUTE};
+use rtnetlink::packet::constants::{AF_INET, RTN_UNICAST};
+use rtnetlink::packet::route::nlas::Nla;
+use rtnetlink::packet::{RouteMessage, RtnlMessage};
+use rtnetlink::proto::Connection;
+use rtnetlink::sys::{AsyncSocket, SocketAddr};
+use rtnetlink::IpVersion;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::Result;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A default route, identified like the kernel does.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct DefaultRoute {
+    family: IpFamily,
+    table: u32,
+    priority: Option<u32>,
+    gateway: Option<Vec<u8>>,
+    oif: Option<u32>,
+}
+
+/// Watches the connectivity per address family.
+///
+/// The connectivity is classified from the addresses of the interfaces that
+/// are up and running and from the default routes through them, without
+/// sending any packets. An event is emitted for each family whose
+/// connectivity changed, starting with the initial connectivity unless it is
+/// [`Offline`](Connectivity::Offline).
+pub struct ConnectivityWatcher<T> {
+    watcher: IfWatcher<T>,
+    conn: Connection<RtnlMessage, T>,
+    messages: MessageStream,
+    routes: FnvHashSet<DefaultRoute>,
+    synced: bool,
+    connectivity: [Connectivity; 2],
+    queue: VecDeque<ConnectivityEvent>,
+}
+
+impl<T> std::fmt::Debug for ConnectivityWatcher<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ConnectivityWatcher")
+            .field("connectivity", &self.connectivity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> ConnectivityWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        let watcher = IfWatcher::new()?;
+        let (mut conn, handle, messages) = rtnetlink::new_connection_with_socket::<T>()?;
+        let addr = SocketAddr::new(0, RTMGRP_IPV4_ROUTE | RTMGRP_IPV6_ROUTE);
+        conn.socket_mut().socket_mut().bind(&addr)?;
+        let get_v4_routes_stream = handle
+            .route()
+            .get(IpVersion::V4)
+            .execute()
+            .map_ok(RtnlMessage::NewRoute);
+        let dump = sequential_dump(get_v4_routes_stream, move || {
+            handle
+                .route()
+                .get(IpVersion::V6)
+                .execute()
+                .map_ok(RtnlMessage::NewRoute)
+        });
+        let messages = message_stream(dump, messages);
+        Ok(Self {
+            watcher,
+            conn,
+            messages,
+            routes: Default::default(),
+            synced: false,
+            connectivity: Default::default(),
+            queue: Default::default(),
+        })
+    }
+
+    /// The current connectivity of `family`.
+    pub fn connectivity(&self, family: IpFamily) -> Connectivity {
+        self.connectivity[family as usize]
+    }
+
+    /// Whether the interface with `index` is up and running. Loopback
+    /// interfaces do not provide connectivity.
+    fn is_up(&self, index: u32) -> bool {
+        self.watcher
+            .link_flags
+            .get(&index)
+            .is_some_and(|flags| flags.up && flags.running && !flags.loopback)
+    }
+
+    fn update(&mut self) {
+        for family in [IpFamily::V4, IpFamily::V6] {
+            let addrs = self
+                .watcher
+                .addresses()
+                .filter(|addr| addr.if_index.is_some_and(|index| self.is_up(index)));
+            // Multipath routes have no single output interface and are
+            // assumed to be usable. `Option::is_none_or` needs Rust 1.82.
+            #[allow(clippy::unnecessary_map_or)]
+            let default_route = self.routes.iter().any(|route| {
+                route.family == family && route.oif.map_or(true, |index| self.is_up(index))
+            });
+            let new = crate::classify(family, addrs, default_route);
+            let old = std::mem::replace(&mut self.connectivity[family as usize], new);
+            if old != new {
+                self.queue.push_back(ConnectivityEvent { family, old, new });
+            }
+        }
+    }
+
+    fn add_route(&mut self, msg: RouteMessage) {
+        if let Some(route) = parse_default_route(msg) {
+            self.routes.insert(route);
+        }
+    }
+
+    fn rem_route(&mut self, msg: RouteMessage) {
+        if let Some(route) = parse_default_route(msg) {
+            self.routes.remove(&route);
+        }
+    }
+
+    /// Poll for a change of the connectivity.
+    pub fn poll_connectivity_event(&mut self, cx: &mut Context) -> Poll<Result<ConnectivityEvent>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            if Pin::new(&mut self.conn).poll(cx).is_ready() {
+                return Poll::Ready(Err(socket_err()));
+            }
+            while let Poll::Ready(message) = self.messages.poll_next_unpin(cx) {
+                match message.ok_or_else(socket_err)?? {
+                    Some((RtnlMessage::NewRoute(msg), _)) => self.add_route(msg),
+                    Some((RtnlMessage::DelRoute(msg), _)) => self.rem_route(msg),
+                    Some(_) => {}
+                    None => self.synced = true,
+                }
+            }
+            // The link flags are updated while polling for address events.
+            while let Poll::Ready(event) = self.watcher.poll_address_event(cx) {
+                event?;
+            }
+            // Classify once the current routes, links and addresses are known.
+            if !self.synced || self.watcher.poll_synced(cx)?.is_pending() {
+                return Poll::Pending;
+            }
+            self.update();
+            if self.queue.is_empty() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+/// Parses a unicast route to `0.0.0.0/0` or `::/0`.
+fn parse_default_route(msg: RouteMessage) -> Option<DefaultRoute> {
+    let header = &msg.header;
+    if header.destination_prefix_length != 0 || header.kind != RTN_UNICAST {
+        return None;
+    }
+    let mut route = DefaultRoute {
+        family: match header.address_family as u16 {
+            AF_INET => IpFamily::V4,
+            _ => IpFamily::V6,
+        },
+        table: header.table.into(),
+        priority: None,
+        gateway: None,
+        oif: None,
+    };
+    for nla in msg.nlas {
+        match nla {
+            Nla::Table(table) => route.table = table,
+            Nla::Priority(priority) => route.priority = Some(priority),
+            Nla::Gateway(gateway) => route.gateway = Some(gateway),
+            Nla::Oif(oif) => route.oif = Some(oif),
+            _ => {}
+        }
+    }
+    Some(route)
+}
+
+impl<T> Stream for ConnectivityWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    type Item = Result<ConnectivityEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_connectivity_event(cx).map(Some)
+    }
+}
+
+impl<T> FusedStream for ConnectivityWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
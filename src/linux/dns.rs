@@ -0,0 +1,263 @@
+//! Watching of the DNS resolver configuration.
+use super::inotify::{Inotify, Watch};
+use futures::stream::{FusedStream, Stream};
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Result};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Default location of the resolver configuration.
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Name servers and search domains of the resolver configuration.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct DnsConfig {
+    /// Addresses of the name servers, in order of preference.
+    pub nameservers: Vec<IpAddr>,
+    /// Domains appended to names that are not fully qualified.
+    pub search: Vec<String>,
+}
+
+impl DnsConfig {
+    /// Parses the `nameserver`, `search` and `domain` lines of a
+    /// `resolv.conf(5)` file. The zone index of link-local name servers is
+    /// dropped.
+    pub fn parse(contents: &str) -> Self {
+        let mut config = Self::default();
+        for line in contents.lines() {
+            let mut words = line
+                .split(|c: char| c.is_whitespace())
+                .filter(|word| !word.is_empty());
+            match words.next() {
+                Some("nameserver") => {
+                    let Some(addr) = words.next() else {
+                        continue;
+                    };
+                    let addr = addr.split_once('%').map_or(addr, |(addr, _)| addr);
+                    if let Ok(addr) = addr.parse() {
+                        config.nameservers.push(addr);
+                    }
+                }
+                // The last `search` or `domain` line takes effect.
+                Some("search" | "domain") => {
+                    config.search = words
+                        .take_while(|word| !word.starts_with(['#', ';']))
+                        .map(Into::into)
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+        config
+    }
+}
+
+/// Watches the name servers and search domains in `/etc/resolv.conf`.
+///
+/// The file and, if it links to another file such as the stub file of
+/// `systemd-resolved`, the directory of that file are watched with inotify.
+/// The current configuration is reported first, followed by every change.
+#[derive(Debug)]
+pub struct DnsWatcher {
+    path: PathBuf,
+    changes: Inotify,
+    config: Option<DnsConfig>,
+    queue: VecDeque<DnsConfig>,
+}
+
+impl DnsWatcher {
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        Self::with_path(RESOLV_CONF)
+    }
+
+    /// Create a watcher for the resolver configuration at `path`.
+    pub fn with_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let watch_path = path.clone();
+        let changes = Inotify::new(move || watches(&watch_path), Duration::ZERO)?;
+        Ok(Self {
+            path,
+            changes,
+            config: None,
+            queue: Default::default(),
+        })
+    }
+
+    /// The current configuration, once it has been read.
+    pub fn config(&self) -> Option<&DnsConfig> {
+        self.config.as_ref()
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        let config = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => DnsConfig::parse(&contents),
+            Err(err) if err.kind() == ErrorKind::NotFound => DnsConfig::default(),
+            Err(err) => return Err(err),
+        };
+        if self.config.as_ref() != Some(&config) {
+            self.config = Some(config.clone());
+            self.queue.push_back(config);
+        }
+        Ok(())
+    }
+
+    /// Poll for a change of the configuration, returning the new
+    /// configuration.
+    pub fn poll_dns_event(&mut self, cx: &mut Context) -> Poll<Result<DnsConfig>> {
+        loop {
+            if let Some(config) = self.queue.pop_front() {
+                return Poll::Ready(Ok(config));
+            }
+            if self.config.is_none() {
+                self.reload()?;
+                continue;
+            }
+            match self.changes.poll_change(cx) {
+                Poll::Ready(Some(())) => self.reload()?,
+                Poll::Ready(None) => {
+                    return Poll::Ready(Err(Error::other("inotify watcher stopped")))
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl Stream for DnsWatcher {
+    type Item = Result<DnsConfig>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_dns_event(cx).map(Some)
+    }
+}
+
+impl FusedStream for DnsWatcher {
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
+
+/// Watches the file at `path` and, if it is a symbolic link, its target in
+/// the directory of the target, since files are usually replaced by renaming a
+/// new file over them. The directory of `path` is only watched while it does
+/// not exist.
+fn watches(path: &Path) -> Vec<Watch> {
+    let entry = |path: &Path| {
+        Some(Watch {
+            path: path.parent()?.to_path_buf(),
+            mask: libc::IN_CLOSE_WRITE
+                | libc::IN_CREATE
+                | libc::IN_DELETE
+                | libc::IN_MOVED_FROM
+                | libc::IN_MOVED_TO,
+            names: Some(vec![path.file_name()?.to_owned()]),
+        })
+    };
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return entry(path).into_iter().collect();
+    };
+    // Replacing the file or link changes its link count.
+    let mut watches = vec![Watch {
+        path: path.to_path_buf(),
+        mask: libc::IN_CLOSE_WRITE
+            | libc::IN_ATTRIB
+            | libc::IN_DELETE_SELF
+            | libc::IN_MOVE_SELF
+            | libc::IN_DONT_FOLLOW,
+        names: None,
+    }];
+    if metadata.file_type().is_symlink() {
+        let target = std::fs::canonicalize(path).ok().or_else(|| {
+            let link = std::fs::read_link(path).ok()?;
+            Some(path.parent()?.join(link))
+        });
+        watches.extend(target.as_deref().and_then(entry));
+    }
+    watches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DnsConfig, DnsWatcher};
+    use futures::StreamExt;
+
+    #[test]
+    fn test_parse() {
+        let config = DnsConfig::parse(
+            "# Generated by NetworkManager\n\
+             domain example.org\n\
+             search example.com lan # comment\n\
+             nameserver 192.168.1.1\n\
+             nameserver fe80::1%eth0\n\
+             nameserver\tinvalid\n\
+             options edns0 trust-ad\n",
+        );
+        assert_eq!(
+            config,
+            DnsConfig {
+                nameservers: vec!["192.168.1.1".parse().unwrap(), "fe80::1".parse().unwrap()],
+                search: vec!["example.com".into(), "lan".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_dns_watch() {
+        let dir = std::env::temp_dir().join(format!("if-watch-dns-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("resolv.conf");
+        std::fs::write(&path, "nameserver 10.0.0.1\n").unwrap();
+        let mut watcher = DnsWatcher::with_path(&path).unwrap();
+        smol::block_on(async {
+            let config = watcher.next().await.unwrap().unwrap();
+            assert_eq!(
+                config.nameservers,
+                ["10.0.0.1".parse::<std::net::IpAddr>().unwrap()]
+            );
+            // Replace the file like `systemd-resolved` does.
+            let tmp = dir.join("resolv.conf.tmp");
+            std::fs::write(&tmp, "nameserver 10.0.0.2\nsearch lan\n").unwrap();
+            std::fs::rename(&tmp, &path).unwrap();
+            let config = watcher.next().await.unwrap().unwrap();
+            assert_eq!(
+                config.nameservers,
+                ["10.0.0.2".parse::<std::net::IpAddr>().unwrap()]
+            );
+            assert_eq!(config.search, ["lan"]);
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dns_watch_symlink() {
+        let dir = std::env::temp_dir().join(format!("if-watch-dns-link-{}", std::process::id()));
+        let stub = dir.join("resolve");
+        std::fs::create_dir_all(&stub).unwrap();
+        let target = stub.join("stub-resolv.conf");
+        std::fs::write(&target, "nameserver 10.0.0.1\n").unwrap();
+        let path = dir.join("resolv.conf");
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+        let mut watcher = DnsWatcher::with_path(&path).unwrap();
+        smol::block_on(async {
+            watcher.next().await.unwrap().unwrap();
+            // Other files in the directory of the target are ignored.
+            std::fs::write(stub.join("other"), "").unwrap();
+            let tmp = stub.join("stub-resolv.conf.tmp");
+            std::fs::write(&tmp, "nameserver 10.0.0.2\n").unwrap();
+            std::fs::rename(&tmp, &target).unwrap();
+            let config = watcher.next().await.unwrap().unwrap();
+            assert_eq!(
+                config.nameservers,
+                ["10.0.0.2".parse::<std::net::IpAddr>().unwrap()]
+            );
+        });
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(watcher.poll_next_unpin(&mut cx).is_pending());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+//! Watching of files and directories with inotify.
+use fnv::FnvHashMap;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use std::ffi::{CString, OsStr, OsString};
+use std::io::{Error, ErrorKind, Result};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Length of `struct inotify_event` without the name.
+const EVENT_LEN: usize = std::mem::size_of::<libc::inotify_event>();
+
+/// A file or directory to watch.
+#[derive(Debug)]
+pub(super) struct Watch {
+    /// Path of the file or directory.
+    pub path: PathBuf,
+    /// Events to watch for, see `inotify(7)`.
+    pub mask: u32,
+    /// Names of the entries of a directory whose events are reported, all of
+    /// them if `None`. Events of the file or directory itself are always
+    /// reported.
+    pub names: Option<Vec<OsString>>,
+}
+
+/// Reports changes of files and directories, which are read from inotify on a
+/// background thread that stops when this is dropped.
+#[derive(Debug)]
+pub(super) struct Inotify {
+    changes: mpsc::Receiver<()>,
+    /// An eventfd signalled to stop the background thread.
+    stop: OwnedFd,
+}
+
+impl Inotify {
+    /// Watch the files and directories returned by `watches`, which is called
+    /// again after every change so that the watches follow files that are
+    /// replaced or created. Changes are reported after `settle` has passed.
+    pub(super) fn new<F>(mut watches: F, settle: Duration) -> Result<Self>
+    where
+        F: FnMut() -> Vec<Watch> + Send + 'static,
+    {
+        let inotify = inotify_init()?;
+        let mut state = Watches::default();
+        // Watch before returning so that no change is missed.
+        state.update(&inotify, watches())?;
+        let stop = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if stop < 0 {
+            return Err(Error::last_os_error());
+        }
+        let stop = unsafe { OwnedFd::from_raw_fd(stop) };
+        let task_stop = stop.try_clone()?;
+        let (tx, changes) = mpsc::channel(1);
+        std::thread::spawn(move || background_task(inotify, task_stop, state, watches, settle, tx));
+        Ok(Self { changes, stop })
+    }
+
+    /// Poll for a change. Returns `None` if watching failed, which is logged.
+    pub(super) fn poll_change(&mut self, cx: &mut Context) -> Poll<Option<()>> {
+        self.changes.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        let one = 1u64;
+        unsafe { libc::write(self.stop.as_raw_fd(), (&one as *const u64).cast(), 8) };
+    }
+}
+
+/// The watches that have been added and the names they report.
+#[derive(Debug, Default)]
+struct Watches(FnvHashMap<libc::c_int, Option<Vec<OsString>>>);
+
+impl Watches {
+    /// Adds `watches` and removes the ones that are no longer needed. Paths
+    /// that do not exist are skipped.
+    fn update(&mut self, inotify: &OwnedFd, watches: Vec<Watch>) -> Result<()> {
+        let mut added = FnvHashMap::<_, Option<Vec<OsString>>>::default();
+        for watch in watches {
+            let path = CString::new(watch.path.as_os_str().as_bytes())?;
+            let wd =
+                unsafe { libc::inotify_add_watch(inotify.as_raw_fd(), path.as_ptr(), watch.mask) };
+            if wd < 0 {
+                let err = Error::last_os_error();
+                if err.kind() == ErrorKind::NotFound {
+                    continue;
+                }
+                return Err(err);
+            }
+            // Watches of the same file or directory share a descriptor.
+            match (added.entry(wd).or_insert(Some(Vec::new())), watch.names) {
+                (Some(names), Some(more)) => names.extend(more),
+                (names, _) => *names = None,
+            }
+        }
+        for wd in self.0.keys() {
+            if !added.contains_key(wd) {
+                unsafe { libc::inotify_rm_watch(inotify.as_raw_fd(), *wd) };
+            }
+        }
+        self.0 = added;
+        Ok(())
+    }
+
+    /// Whether an event is reported.
+    fn matches(&self, wd: libc::c_int, mask: u32, name: &OsStr) -> bool {
+        if mask & libc::IN_Q_OVERFLOW != 0 {
+            return true;
+        }
+        match self.0.get(&wd) {
+            Some(Some(names)) => name.is_empty() || names.iter().any(|other| other == name),
+            Some(None) => true,
+            None => false,
+        }
+    }
+}
+
+fn inotify_init() -> Result<OwnedFd> {
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Iterates over the watch descriptors, masks and names of the events in
+/// `buf`.
+fn events(buf: &[u8]) -> impl Iterator<Item = (libc::c_int, u32, &OsStr)> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let header = buf.get(offset..offset + EVENT_LEN)?;
+        let event =
+            unsafe { std::ptr::read_unaligned(header.as_ptr().cast::<libc::inotify_event>()) };
+        let start = offset + EVENT_LEN;
+        offset = start + event.len as usize;
+        let name = buf.get(start..offset)?;
+        let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+        Some((event.wd, event.mask, OsStr::from_bytes(name)))
+    })
+}
+
+/// Waits until `inotify` is readable or `timeout` has passed, returning
+/// whether `stop` has been signalled.
+fn wait(inotify: &OwnedFd, stop: &OwnedFd, timeout: Option<Duration>) -> Result<bool> {
+    let mut fds = [
+        libc::pollfd {
+            fd: stop.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: inotify.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    // Only the stop signal is waited for while settling.
+    let (nfds, timeout) = match timeout {
+        Some(timeout) => (1, timeout.as_millis() as libc::c_int),
+        None => (2, -1),
+    };
+    loop {
+        if unsafe { libc::poll(fds.as_mut_ptr(), nfds, timeout) } >= 0 {
+            return Ok(fds[0].revents != 0);
+        }
+        let err = Error::last_os_error();
+        if err.kind() != ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Notifies `tx` of changes, updating the watches after every change.
+fn background_task<F>(
+    inotify: OwnedFd,
+    stop: OwnedFd,
+    mut state: Watches,
+    mut watches: F,
+    settle: Duration,
+    mut tx: mpsc::Sender<()>,
+) where
+    F: FnMut() -> Vec<Watch>,
+{
+    let mut buf = [0u8; 4096];
+    loop {
+        match wait(&inotify, &stop, None) {
+            Ok(false) => {}
+            Ok(true) => return,
+            Err(err) => {
+                log::error!("error polling inotify: {}", err);
+                return;
+            }
+        }
+        let len = unsafe { libc::read(inotify.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        if len < 0 {
+            let err = Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            log::error!("error reading inotify events: {}", err);
+            return;
+        }
+        let changed =
+            events(&buf[..len as usize]).any(|(wd, mask, name)| state.matches(wd, mask, name));
+        if let Err(err) = state.update(&inotify, watches()) {
+            log::error!("error watching files: {}", err);
+            return;
+        }
+        if !changed {
+            continue;
+        }
+        if !settle.is_zero() && !matches!(wait(&inotify, &stop, Some(settle)), Ok(false)) {
+            return;
+        }
+        match tx.try_send(()) {
+            Err(err) if err.is_disconnected() => return,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Inotify, Watch};
+    use futures::future::poll_fn;
+    use std::time::Duration;
+
+    #[test]
+    fn test_inotify_names() {
+        let dir = std::env::temp_dir().join(format!("if-watch-inotify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let watch_dir = dir.clone();
+        let mut inotify = Inotify::new(
+            move || {
+                vec![Watch {
+                    path: watch_dir.clone(),
+                    mask: libc::IN_CLOSE_WRITE,
+                    names: Some(vec!["watched".into()]),
+                }]
+            },
+            Duration::ZERO,
+        )
+        .unwrap();
+        std::fs::write(dir.join("other"), "").unwrap();
+        std::fs::write(dir.join("watched"), "").unwrap();
+        smol::block_on(async {
+            assert_eq!(poll_fn(|cx| inotify.poll_change(cx)).await, Some(()));
+        });
+        // The change of the other file has not been reported.
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(inotify.poll_change(&mut cx).is_pending());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+//! Watching of multicast group memberships.
+use super::raw::{self, attr, messages, parse_u32, NLM_F_DUMP};
+use super::{parse_ip, IfWatcher};
+use crate::IfLink;
+use fnv::FnvHashMap;
+use futures::ready;
+use futures::stream::{FusedStream, Stream};
+use rtnetlink::packet::constants::{AF_INET, AF_INET6, IFA_MULTICAST, RTM_GETMULTICAST};
+use rtnetlink::sys::protocols::NETLINK_ROUTE;
+use rtnetlink::sys::AsyncSocket;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::io::Result;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+const RTM_NEWMULTICAST: u16 = 56;
+const RTM_DELMULTICAST: u16 = 57;
+const RTNLGRP_IPV4_MCADDR: u32 = 37;
+const RTNLGRP_IPV6_MCADDR: u32 = 38;
+/// Length of `struct ifaddrmsg`.
+const IFADDRMSG_LEN: usize = 8;
+
+/// A multicast group joined on an interface.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct MulticastMembership {
+    /// Index of the interface.
+    pub if_index: u32,
+    /// Name of the interface.
+    pub if_name: String,
+    /// Address of the group.
+    pub group: IpAddr,
+}
+
+/// A multicast change event.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum MulticastEvent {
+    /// An interface can send and receive multicast, since it is up, running
+    /// and supports multicast.
+    Enabled(IfLink),
+    /// An interface cannot send and receive multicast anymore.
+    Disabled(IfLink),
+    /// A group has been joined on an interface.
+    Joined(MulticastMembership),
+    /// A group has been left on an interface.
+    Left(MulticastMembership),
+}
+
+/// Watches which interfaces can do multicast and the multicast groups joined
+/// on them, see `ip-maddress(8)`.
+///
+/// Memberships are those of the host, not only of this process. The current
+/// interfaces and memberships are reported as `Enabled` and `Joined` events
+/// first. Changes of memberships are only reported by Linux 6.13 and later.
+pub struct MulticastWatcher<T> {
+    watcher: IfWatcher<T>,
+    socket: T,
+    /// Loads the current memberships, before notifications are received.
+    load: Option<Load>,
+    links: FnvHashMap<u32, IfLink>,
+    memberships: BTreeMap<(u32, IpAddr), MulticastMembership>,
+    changes: VecDeque<(bool, u32, IpAddr)>,
+    queue: VecDeque<MulticastEvent>,
+}
+
+impl<T> std::fmt::Debug for MulticastWatcher<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("MulticastWatcher")
+            .field("links", &self.links)
+            .field("memberships", &self.memberships)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The interface indices and groups of the current memberships.
+type Load = Pin<Box<dyn Future<Output = Result<Vec<(u32, IpAddr)>>> + Send>>;
+
+impl<T> MulticastWatcher<T>
+where
+    T: AsyncSocket + Unpin + Send + Sync + 'static,
+{
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        let watcher = IfWatcher::new()?;
+        // `rtnetlink` cannot parse multicast address messages, so they are
+        // received on a separate socket.
+        let mut socket = T::new(NETLINK_ROUTE)?;
+        socket.socket_mut().bind_auto()?;
+        socket.socket_mut().add_membership(RTNLGRP_IPV4_MCADDR)?;
+        socket.socket_mut().add_membership(RTNLGRP_IPV6_MCADDR)?;
+        // The current memberships are loaded on a separate socket after
+        // joining the groups, so that no change is missed.
+        let mut ctrl = T::new(NETLINK_ROUTE)?;
+        ctrl.socket_mut().bind_auto()?;
+        Ok(Self {
+            watcher,
+            socket,
+            load: Some(Box::pin(load(ctrl))),
+            links: Default::default(),
+            memberships: Default::default(),
+            changes: Default::default(),
+            queue: Default::default(),
+        })
+    }
+
+    /// Iterate over the interfaces that can do multicast.
+    pub fn links(&self) -> impl Iterator<Item = &IfLink> {
+        self.links.values()
+    }
+
+    /// Iterate over the current memberships.
+    pub fn memberships(&self) -> impl Iterator<Item = &MulticastMembership> {
+        self.memberships.values()
+    }
+
+    fn update(&mut self) {
+        let watcher = &self.watcher;
+        for (index, link) in &watcher.links {
+            if is_enabled(watcher, *index) && !self.links.contains_key(index) {
+                self.links.insert(*index, link.clone());
+                self.queue.push_back(MulticastEvent::Enabled(link.clone()));
+            }
+        }
+        while let Some((joined, if_index, group)) = self.changes.pop_front() {
+            let key = (if_index, group);
+            if !joined {
+                if let Some(membership) = self.memberships.remove(&key) {
+                    self.queue.push_back(MulticastEvent::Left(membership));
+                }
+                continue;
+            }
+            // Memberships keep the name of their interface, which is gone
+            // once they are left because the interface has been removed.
+            let Entry::Vacant(entry) = self.memberships.entry(key) else {
+                continue;
+            };
+            let if_name = watcher.links.get(&if_index).map(|link| &link.if_name);
+            let membership = entry.insert(MulticastMembership {
+                if_index,
+                if_name: if_name.cloned().unwrap_or_default(),
+                group,
+            });
+            self.queue
+                .push_back(MulticastEvent::Joined(membership.clone()));
+        }
+        // The kernel does not notify about all memberships of removed
+        // interfaces.
+        let removed: Vec<_> = self
+            .memberships
+            .keys()
+            .copied()
+            .filter(|(index, _)| !watcher.links.contains_key(index))
+            .collect();
+        for key in removed {
+            if let Some(membership) = self.memberships.remove(&key) {
+                self.queue.push_back(MulticastEvent::Left(membership));
+            }
+        }
+        let disabled: Vec<_> = self
+            .links
+            .keys()
+            .copied()
+            .filter(|index| !is_enabled(watcher, *index))
+            .collect();
+        for index in disabled {
+            if let Some(link) = self.links.remove(&index) {
+                self.queue.push_back(MulticastEvent::Disabled(link));
+            }
+        }
+    }
+
+    /// Poll for a multicast change event.
+    pub fn poll_multicast_event(&mut self, cx: &mut Context) -> Poll<Result<MulticastEvent>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            if let Some(load) = &mut self.load {
+                let memberships = ready!(load.as_mut().poll(cx));
+                self.load = None;
+                let memberships = memberships?.into_iter();
+                self.changes
+                    .extend(memberships.map(|(index, group)| (true, index, group)));
+            }
+            while let Poll::Ready(result) = self.socket.poll_recv_from_full(cx) {
+                #[cfg(feature = "metrics")]
+                if let Err(err) = &result {
+                    crate::stats::recv_error(super::BACKEND, err);
+                }
+                let (buf, _) = result?;
+                for (kind, payload) in messages(&buf) {
+                    let joined = match kind {
+                        RTM_NEWMULTICAST => true,
+                        RTM_DELMULTICAST => false,
+                        _ => continue,
+                    };
+                    if let Some((index, group)) = parse_membership(payload) {
+                        self.changes.push_back((joined, index, group));
+                    }
+                }
+            }
+            // The links are updated while polling for address events.
+            while let Poll::Ready(event) = self.watcher.poll_address_event(cx) {
+                event?;
+            }
+            if self.watcher.poll_synced(cx)?.is_pending() {
+                return Poll::Pending;
+            }
+            self.update();
+            if self.queue.is_empty() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+async fn load<T: AsyncSocket>(ctrl: T) -> Result<Vec<(u32, IpAddr)>> {
+    let mut memberships = Vec::new();
+    for family in [AF_INET, AF_INET6] {
+        let mut msg = [0; IFADDRMSG_LEN];
+        msg[0] = family as u8;
+        let replies = match raw::request(&ctrl, RTM_GETMULTICAST, NLM_F_DUMP, &msg).await {
+            // Older kernels cannot dump IPv4 memberships.
+            Err(err) if err.raw_os_error() == Some(libc::EOPNOTSUPP) => Vec::new(),
+            replies => replies?,
+        };
+        memberships.extend(replies.iter().filter_map(|reply| parse_membership(reply)));
+    }
+    Ok(memberships)
+}
+
+/// Whether the interface with `index` can send and receive multicast.
+fn is_enabled<T>(watcher: &IfWatcher<T>, index: u32) -> bool {
+    watcher
+        .link_flags
+        .get(&index)
+        .is_some_and(|flags| flags.up && flags.running && flags.multicast)
+}
+
+/// Parses the interface index and group of a multicast address message.
+fn parse_membership(msg: &[u8]) -> Option<(u32, IpAddr)> {
+    let family = *msg.first()?;
+    let index = msg.get(4..IFADDRMSG_LEN).and_then(parse_u32)?;
+    let attrs = msg.get(IFADDRMSG_LEN..)?;
+    let group = parse_ip(family, attr(attrs, IFA_MULTICAST)?)?;
+    Some((index, group))
+}
+
+impl<T> Stream for MulticastWatcher<T>
+where
+    T: AsyncSocket + Unpin + Send + Sync + 'static,
+{
+    type Item = Result<MulticastEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_multicast_event(cx).map(Some)
+    }
+}
+
+impl<T> FusedStream for MulticastWatcher<T>
+where
+    T: AsyncSocket + Unpin + Send + Sync + 'static,
+{
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
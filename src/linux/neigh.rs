@@ -0,0 +1,342 @@
+//! Watching of the neighbor (ARP and NDP) table.
+use super::{message_stream, parse_ip, sequential_dump, socket_err, MessageStream};
+use fnv::FnvHashMap;
+use futures::ready;
+use futures::stream::{FusedStream, Stream, TryStreamExt};
+use futures::StreamExt;
+use rtnetlink::constants::{RTMGRP_LINK, RTMGRP_NEIGH};
+use rtnetlink::packet::constants::{
+    NUD_DELAY, NUD_FAILED, NUD_INCOMPLETE, NUD_NOARP, NUD_PERMANENT, NUD_PROBE, NUD_REACHABLE,
+    NUD_STALE,
+};
+use rtnetlink::packet::link::nlas::Nla as LinkNla;
+use rtnetlink::packet::neighbour::nlas::Nla;
+use rtnetlink::packet::{LinkMessage, NeighbourMessage, RtnlMessage};
+use rtnetlink::proto::Connection;
+use rtnetlink::sys::{AsyncSocket, SocketAddr};
+use std::collections::hash_map::Entry;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::Result;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// State of a neighbor cache entry, see `NUD_*` in `ip-neighbour(8)`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum NudState {
+    /// Address resolution is in progress.
+    Incomplete,
+    /// The neighbor is known to be reachable.
+    Reachable,
+    /// The entry is valid but its reachability has not been confirmed
+    /// recently.
+    Stale,
+    /// Reachability confirmation is delayed.
+    Delay,
+    /// Reachability is being confirmed.
+    Probe,
+    /// Address resolution has failed.
+    Failed,
+    /// The entry is valid without address resolution.
+    NoArp,
+    /// The entry has been configured statically.
+    Permanent,
+    /// Any other state.
+    Other(u16),
+}
+
+impl From<u16> for NudState {
+    fn from(state: u16) -> Self {
+        match state {
+            NUD_INCOMPLETE => Self::Incomplete,
+            NUD_REACHABLE => Self::Reachable,
+            NUD_STALE => Self::Stale,
+            NUD_DELAY => Self::Delay,
+            NUD_PROBE => Self::Probe,
+            NUD_FAILED => Self::Failed,
+            NUD_NOARP => Self::NoArp,
+            NUD_PERMANENT => Self::Permanent,
+            state => Self::Other(state),
+        }
+    }
+}
+
+/// An entry of the neighbor table.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Neighbor {
+    /// IP address of the neighbor.
+    pub ip: IpAddr,
+    /// Link layer address of the neighbor, if it has been resolved.
+    pub mac: Option<Vec<u8>>,
+    /// Index of the interface the neighbor is reachable through.
+    pub if_index: u32,
+    /// Name of the interface the neighbor is reachable through.
+    pub if_name: String,
+    /// State of the entry.
+    pub state: NudState,
+}
+
+/// A neighbor table change event.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum NeighborEvent {
+    /// A neighbor has been added.
+    Added(Neighbor),
+    /// The link layer address or state of a neighbor has changed.
+    Changed(Neighbor),
+    /// A neighbor has been removed.
+    Removed(Neighbor),
+}
+
+impl NeighborEvent {
+    /// The neighbor that has been added, changed or removed.
+    pub fn neighbor(&self) -> &Neighbor {
+        match self {
+            Self::Added(neigh) | Self::Changed(neigh) | Self::Removed(neigh) => neigh,
+        }
+    }
+}
+
+/// Watches the IPv4 (ARP) and IPv6 (NDP) neighbor table.
+///
+/// The current neighbors are reported as `Added` events first.
+pub struct NeighborWatcher<T> {
+    conn: Connection<RtnlMessage, T>,
+    messages: MessageStream,
+    links: FnvHashMap<u32, String>,
+    neighbors: FnvHashMap<(u32, IpAddr), Neighbor>,
+    queue: VecDeque<NeighborEvent>,
+    synced: bool,
+}
+
+impl<T> std::fmt::Debug for NeighborWatcher<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("NeighborWatcher")
+            .field("neighbors", &self.neighbors)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> NeighborWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        let (mut conn, handle, messages) = rtnetlink::new_connection_with_socket::<T>()?;
+        let addr = SocketAddr::new(0, RTMGRP_LINK | RTMGRP_NEIGH);
+        conn.socket_mut().socket_mut().bind(&addr)?;
+        let get_links_stream = handle.link().get().execute().map_ok(RtnlMessage::NewLink);
+        let dump = sequential_dump(get_links_stream, move || {
+            handle
+                .neighbours()
+                .get()
+                .execute()
+                .map_ok(RtnlMessage::NewNeighbour)
+        });
+        let messages = message_stream(dump, messages);
+        Ok(Self {
+            conn,
+            messages,
+            links: Default::default(),
+            neighbors: Default::default(),
+            queue: Default::default(),
+            synced: false,
+        })
+    }
+
+    /// Iterate over the current neighbors.
+    pub fn neighbors(&self) -> impl Iterator<Item = &Neighbor> {
+        self.neighbors.values()
+    }
+
+    fn add_link(&mut self, msg: LinkMessage) {
+        let index = msg.header.index;
+        for nla in msg.nlas {
+            if let LinkNla::IfName(name) = nla {
+                self.links.insert(index, name);
+            }
+        }
+    }
+
+    fn rem_link(&mut self, msg: LinkMessage) {
+        self.links.remove(&msg.header.index);
+    }
+
+    fn add_neighbor(&mut self, msg: NeighbourMessage) {
+        let Some(neigh) = self.parse_neighbor(msg) else {
+            return;
+        };
+        match self.neighbors.entry((neigh.if_index, neigh.ip)) {
+            Entry::Vacant(entry) => {
+                entry.insert(neigh.clone());
+                self.queue.push_back(NeighborEvent::Added(neigh));
+            }
+            Entry::Occupied(mut entry) => {
+                // The kernel also notifies about refreshed entries.
+                if *entry.get() != neigh {
+                    entry.insert(neigh.clone());
+                    self.queue.push_back(NeighborEvent::Changed(neigh));
+                }
+            }
+        }
+    }
+
+    fn rem_neighbor(&mut self, msg: NeighbourMessage) {
+        let Some(neigh) = self.parse_neighbor(msg) else {
+            return;
+        };
+        if let Some(neigh) = self.neighbors.remove(&(neigh.if_index, neigh.ip)) {
+            self.queue.push_back(NeighborEvent::Removed(neigh));
+        }
+    }
+
+    /// Parses an IPv4 or IPv6 neighbor, ignoring bridge forwarding entries.
+    fn parse_neighbor(&self, msg: NeighbourMessage) -> Option<Neighbor> {
+        let family = msg.header.family;
+        let mut ip = None;
+        let mut mac = None;
+        for nla in msg.nlas {
+            match nla {
+                Nla::Destination(octets) => ip = parse_ip(family, &octets),
+                Nla::LinkLocalAddress(octets) => mac = Some(octets),
+                _ => {}
+            }
+        }
+        let if_index = msg.header.ifindex;
+        Some(Neighbor {
+            ip: ip?,
+            mac,
+            if_index,
+            if_name: self.links.get(&if_index).cloned().unwrap_or_default(),
+            state: msg.header.state.into(),
+        })
+    }
+
+    fn poll_message(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        if Pin::new(&mut self.conn).poll(cx).is_ready() {
+            return Poll::Ready(Err(socket_err()));
+        }
+        let message = ready!(self.messages.poll_next_unpin(cx)).ok_or_else(socket_err)??;
+        match message {
+            Some((RtnlMessage::NewLink(msg), _)) => self.add_link(msg),
+            Some((RtnlMessage::DelLink(msg), _)) => self.rem_link(msg),
+            Some((RtnlMessage::NewNeighbour(msg), _)) => self.add_neighbor(msg),
+            Some((RtnlMessage::DelNeighbour(msg), _)) => self.rem_neighbor(msg),
+            Some(_) => {}
+            None => self.synced = true,
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Poll until the neighbors present when the watcher was created have been
+    /// loaded. Events for these neighbors remain queued.
+    pub fn poll_synced(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        while !self.synced {
+            ready!(self.poll_message(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Poll for a neighbor change event.
+    pub fn poll_neighbor_event(&mut self, cx: &mut Context) -> Poll<Result<NeighborEvent>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            ready!(self.poll_message(cx))?;
+        }
+    }
+}
+
+impl<T> Stream for NeighborWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    type Item = Result<NeighborEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_neighbor_event(cx).map(Some)
+    }
+}
+
+impl<T> FusedStream for NeighborWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(all(test, feature = "smol"))]
+mod tests {
+    use super::{Neighbor, NeighborEvent, NeighborWatcher, NudState};
+    use rtnetlink::packet::constants::{AF_BRIDGE, AF_INET, NUD_REACHABLE, NUD_STALE};
+    use rtnetlink::packet::link::nlas::Nla as LinkNla;
+    use rtnetlink::packet::neighbour::nlas::Nla;
+    use rtnetlink::packet::{LinkMessage, NeighbourMessage};
+    use rtnetlink::sys::SmolSocket;
+
+    fn neighbor(family: u16, state: u16, mac: [u8; 6]) -> NeighbourMessage {
+        // ip neigh replace 192.0.2.1 lladdr MAC dev eth0 nud STATE
+        let mut msg = NeighbourMessage::default();
+        msg.header.family = family as u8;
+        msg.header.ifindex = 2;
+        msg.header.state = state;
+        msg.nlas = vec![
+            Nla::Destination(vec![192, 0, 2, 1]),
+            Nla::LinkLocalAddress(mac.to_vec()),
+        ];
+        msg
+    }
+
+    #[test]
+    fn test_neighbor_events() {
+        let mut watcher = NeighborWatcher::<SmolSocket>::new().unwrap();
+        let mut link = LinkMessage::default();
+        link.header.index = 2;
+        link.nlas = vec![LinkNla::IfName("eth0".into())];
+        watcher.add_link(link);
+
+        let mac = [2, 0, 0, 0, 0, 1];
+        watcher.add_neighbor(neighbor(AF_INET, NUD_REACHABLE, mac));
+        let mut neigh = Neighbor {
+            ip: "192.0.2.1".parse().unwrap(),
+            mac: Some(mac.to_vec()),
+            if_index: 2,
+            if_name: "eth0".into(),
+            state: NudState::Reachable,
+        };
+        assert_eq!(
+            watcher.queue.pop_front(),
+            Some(NeighborEvent::Added(neigh.clone()))
+        );
+        // Refreshing the entry is not a change.
+        watcher.add_neighbor(neighbor(AF_INET, NUD_REACHABLE, mac));
+        assert!(watcher.queue.is_empty());
+        // Bridge forwarding entries have no IP address.
+        watcher.add_neighbor(neighbor(AF_BRIDGE, NUD_REACHABLE, mac));
+        assert!(watcher.queue.is_empty());
+
+        watcher.add_neighbor(neighbor(AF_INET, NUD_STALE, mac));
+        neigh.state = NudState::Stale;
+        assert_eq!(
+            watcher.queue.pop_front(),
+            Some(NeighborEvent::Changed(neigh.clone()))
+        );
+        let mac = [2, 0, 0, 0, 0, 2];
+        watcher.add_neighbor(neighbor(AF_INET, NUD_STALE, mac));
+        neigh.mac = Some(mac.to_vec());
+        assert_eq!(
+            watcher.queue.pop_front(),
+            Some(NeighborEvent::Changed(neigh.clone()))
+        );
+
+        watcher.rem_neighbor(neighbor(AF_INET, NUD_STALE, mac));
+        assert_eq!(
+            watcher.queue.pop_front(),
+            Some(NeighborEvent::Removed(neigh))
+        );
+        assert!(watcher.neighbors().next().is_none());
+    }
+}
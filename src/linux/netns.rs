@@ -0,0 +1,333 @@
+//! Discovery of named network namespaces and watching of their addresses.
+use super::inotify::{Inotify, Watch};
+use super::{open_socket_in_netns, socket_err, IfWatcher};
+use crate::IfAddressEvent;
+use fnv::{FnvHashMap, FnvHashSet};
+use futures::channel::{mpsc, oneshot};
+use futures::stream::{FusedStream, Stream};
+use futures::{FutureExt, StreamExt};
+use rtnetlink::packet::constants::RTNLGRP_NSID;
+use rtnetlink::packet::{NetlinkMessage, RtnlMessage};
+use rtnetlink::proto::{Connection, NetlinkPayload};
+use rtnetlink::sys::{AsyncSocket, SocketAddr};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::future::Future;
+use std::io::{Error, ErrorKind, Result};
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Directory in which `ip netns` creates named network namespaces.
+const NETNS_RUN_DIR: &str = "/var/run/netns";
+
+/// Delay before rescanning after a change in [`NETNS_RUN_DIR`].
+///
+/// `ip netns add` creates the file before bind mounting the namespace on it,
+/// which does not cause another notification.
+const SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// An event of a [`NetnsWatcher`].
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum NetnsEvent {
+    /// A named network namespace has been discovered.
+    Added(String),
+    /// A named network namespace has disappeared. `Down` events for its
+    /// addresses are emitted before this event.
+    Removed(String),
+    /// An address of the named network namespace has been added, deleted or changed.
+    Address(String, IfAddressEvent),
+}
+
+/// Watches the addresses of all named network namespaces.
+///
+/// Namespaces are discovered in `/var/run/netns`, which is rescanned when its
+/// contents change or the kernel reports a new or deleted namespace id
+/// (`RTM_NEWNSID`, `RTM_DELNSID`).
+pub struct NetnsWatcher<T> {
+    conn: Connection<RtnlMessage, T>,
+    messages: mpsc::UnboundedReceiver<(NetlinkMessage<RtnlMessage>, SocketAddr)>,
+    dir_changes: Inotify,
+    watchers: FnvHashMap<String, IfWatcher<T>>,
+    /// Namespaces whose socket is being opened on a helper thread.
+    opening: FnvHashMap<String, oneshot::Receiver<Result<(OwnedFd, u64)>>>,
+    queue: VecDeque<NetnsEvent>,
+    resync: bool,
+}
+
+impl<T> std::fmt::Debug for NetnsWatcher<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("NetnsWatcher")
+            .field("watchers", &self.watchers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> NetnsWatcher<T>
+where
+    T: AsyncSocket + FromRawFd + Unpin,
+{
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        let (mut conn, _, messages) = rtnetlink::new_connection_with_socket::<T>()?;
+        let socket = conn.socket_mut().socket_mut();
+        socket.bind(&SocketAddr::new(0, 0))?;
+        socket.add_membership(RTNLGRP_NSID)?;
+        let dir_changes = Inotify::new(watches, SETTLE_DELAY)?;
+        Ok(Self {
+            conn,
+            messages,
+            dir_changes,
+            watchers: Default::default(),
+            opening: Default::default(),
+            queue: Default::default(),
+            resync: true,
+        })
+    }
+
+    /// Iterate over the names of the watched namespaces and their watchers.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &IfWatcher<T>)> {
+        self.watchers
+            .iter()
+            .map(|(name, watcher)| (name.as_str(), watcher))
+    }
+
+    fn resync(&mut self) -> Result<()> {
+        let mut names = FnvHashSet::default();
+        match std::fs::read_dir(NETNS_RUN_DIR) {
+            Ok(entries) => {
+                for entry in entries {
+                    names.insert(entry?.file_name().to_string_lossy().into_owned());
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let removed: Vec<_> = self
+            .watchers
+            .keys()
+            .filter(|name| !names.contains(*name))
+            .cloned()
+            .collect();
+        for name in removed {
+            self.remove(name);
+        }
+        self.opening.retain(|name, _| names.contains(name));
+        for name in names {
+            if self.watchers.contains_key(&name) || self.opening.contains_key(&name) {
+                continue;
+            }
+            // Entering the namespace requires a thread, which is not waited
+            // for while polling.
+            let (tx, rx) = oneshot::channel();
+            let path = Path::new(NETNS_RUN_DIR).join(&name);
+            std::thread::spawn(move || tx.send(open_netns(path)));
+            self.opening.insert(name, rx);
+        }
+        Ok(())
+    }
+
+    /// Starts watching `name` once its socket has been opened.
+    fn opened(&mut self, name: String, socket: Result<(OwnedFd, u64)>) {
+        match socket.and_then(|(socket, netns)| IfWatcher::from_netns_socket(socket, netns)) {
+            Ok(watcher) => {
+                self.watchers.insert(name.clone(), watcher);
+                self.queue.push_back(NetnsEvent::Added(name));
+            }
+            // The file exists but the namespace has not been mounted on it
+            // yet. It is picked up by the rescan after the settle delay.
+            Err(err) if err.raw_os_error() == Some(libc::EINVAL) => {}
+            // Other namespaces are still watched if one cannot be, e.g.
+            // because it has been deleted in the meantime.
+            Err(err) => log::warn!("error watching network namespace {}: {}", name, err),
+        }
+    }
+
+    /// Stops watching `name`, emitting `Down` events for its addresses.
+    fn remove(&mut self, name: String) {
+        if let Some(watcher) = self.watchers.remove(&name) {
+            for addr in watcher.addresses() {
+                let event = IfAddressEvent::Down(addr.clone());
+                self.queue
+                    .push_back(NetnsEvent::Address(name.clone(), event));
+            }
+            self.queue.push_back(NetnsEvent::Removed(name));
+        }
+    }
+
+    /// Poll for a namespace or address change event.
+    ///
+    /// If the watcher of a namespace fails, the error is returned and the
+    /// namespace is removed.
+    pub fn poll_netns_event(&mut self, cx: &mut Context) -> Poll<Result<NetnsEvent>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            if Pin::new(&mut self.conn).poll(cx).is_ready() {
+                return Poll::Ready(Err(socket_err()));
+            }
+            while let Poll::Ready(message) = self.messages.poll_next_unpin(cx) {
+                let (message, _) = message.ok_or_else(socket_err)?;
+                match message.payload {
+                    NetlinkPayload::Error(err) => return Poll::Ready(Err(err.to_io())),
+                    NetlinkPayload::InnerMessage(
+                        RtnlMessage::NewNsId(_) | RtnlMessage::DelNsId(_),
+                    ) => self.resync = true,
+                    _ => {}
+                }
+            }
+            // Watching only stops if inotify fails, in which case namespaces
+            // are still discovered through namespace id changes.
+            while let Poll::Ready(Some(())) = self.dir_changes.poll_change(cx) {
+                self.resync = true;
+            }
+            if std::mem::take(&mut self.resync) {
+                self.resync()?;
+                continue;
+            }
+            let mut opened = Vec::new();
+            self.opening.retain(|name, rx| match rx.poll_unpin(cx) {
+                Poll::Ready(socket) => {
+                    let socket =
+                        socket.unwrap_or_else(|_| Err(Error::other("netns thread panicked")));
+                    opened.push((name.clone(), socket));
+                    false
+                }
+                Poll::Pending => true,
+            });
+            for (name, socket) in opened {
+                self.opened(name, socket);
+            }
+            let mut failed = None;
+            for (name, watcher) in &mut self.watchers {
+                match watcher.poll_address_event(cx) {
+                    Poll::Ready(Ok(event)) => {
+                        self.queue
+                            .push_back(NetnsEvent::Address(name.clone(), event));
+                    }
+                    Poll::Ready(Err(err)) => {
+                        failed = Some((name.clone(), err));
+                        break;
+                    }
+                    Poll::Pending => {}
+                }
+            }
+            if let Some((name, err)) = failed {
+                self.remove(name);
+                return Poll::Ready(Err(err));
+            }
+            if self.queue.is_empty() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<T> Stream for NetnsWatcher<T>
+where
+    T: AsyncSocket + FromRawFd + Unpin,
+{
+    type Item = Result<NetnsEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_netns_event(cx).map(Some)
+    }
+}
+
+impl<T> FusedStream for NetnsWatcher<T>
+where
+    T: AsyncSocket + FromRawFd + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
+
+/// Opens a netlink socket in the namespace at `path`, returning it with the
+/// inode number of the namespace.
+fn open_netns(path: PathBuf) -> Result<(OwnedFd, u64)> {
+    let ns = File::open(path)?;
+    let netns = ns.metadata()?.ino();
+    Ok((open_socket_in_netns(&ns)?, netns))
+}
+
+/// Watches [`NETNS_RUN_DIR`], or its parent until it has been created.
+fn watches() -> Vec<Watch> {
+    if Path::new(NETNS_RUN_DIR).is_dir() {
+        let mask = libc::IN_CREATE
+            | libc::IN_DELETE
+            | libc::IN_MOVED_FROM
+            | libc::IN_MOVED_TO
+            | libc::IN_DELETE_SELF;
+        vec![Watch {
+            path: NETNS_RUN_DIR.into(),
+            mask,
+            names: None,
+        }]
+    } else {
+        vec![Watch {
+            path: "/var/run".into(),
+            mask: libc::IN_CREATE,
+            names: Some(vec!["netns".into()]),
+        }]
+    }
+}
+
+#[cfg(all(test, feature = "smol"))]
+mod tests {
+    use super::{open_socket_in_netns, NetnsEvent, NetnsWatcher};
+    use crate::IfAddressEvent;
+    use futures::future::poll_fn;
+    use rtnetlink::sys::SmolSocket;
+    use std::fs::File;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn test_netns_remove() {
+        smol::block_on(async {
+            let mut netns = NetnsWatcher::<SmolSocket>::new().unwrap();
+            // The namespace is mounted on the file later.
+            netns.opened("test".into(), Err(Error::from_raw_os_error(libc::EINVAL)));
+            assert!(netns.watchers.is_empty() && netns.queue.is_empty());
+
+            let socket = open_socket_in_netns(&File::open("/proc/self/ns/net").unwrap());
+            let socket = match socket {
+                // Entering a namespace requires `CAP_SYS_ADMIN`.
+                Err(err) if err.kind() == ErrorKind::PermissionDenied => return,
+                result => result.unwrap(),
+            };
+            netns.opened("test".into(), Ok((socket, 0)));
+            assert_eq!(
+                netns.queue.pop_front(),
+                Some(NetnsEvent::Added("test".into()))
+            );
+            let watcher = netns.watchers.get_mut("test").unwrap();
+            poll_fn(|cx| watcher.poll_synced(cx)).await.unwrap();
+            let mut addrs: Vec<_> = watcher.addresses().cloned().collect();
+            assert!(!addrs.is_empty());
+
+            // The addresses of a removed namespace go down before it is
+            // reported as removed.
+            netns.remove("test".into());
+            assert_eq!(
+                netns.queue.pop_back(),
+                Some(NetnsEvent::Removed("test".into()))
+            );
+            let mut down: Vec<_> = netns
+                .queue
+                .drain(..)
+                .map(|event| match event {
+                    NetnsEvent::Address(name, IfAddressEvent::Down(addr)) if name == "test" => addr,
+                    event => panic!("unexpected event {:?}", event),
+                })
+                .collect();
+            addrs.sort();
+            down.sort();
+            assert_eq!(down, addrs);
+            assert!(netns.watchers.is_empty());
+        });
+    }
+}
@@ -0,0 +1,186 @@
+//! Tracking of the address used to reach the internet.
+use super::{parse_ip, socket_err, IfWatcher};
+use crate::{IfAddress, IpFamily, PrimaryEvent, PROBE_V4, PROBE_V6};
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::stream::{FusedStream, Stream};
+use futures::StreamExt;
+use rtnetlink::constants::{RTMGRP_IPV4_ROUTE, RTMGRP_IPV6_ROUTE};
+use rtnetlink::packet::constants::NLM_F_REQUEST;
+use rtnetlink::packet::route::nlas::Nla;
+use rtnetlink::packet::{NetlinkMessage, RouteMessage, RtnlMessage};
+use rtnetlink::proto::{Connection, NetlinkPayload};
+use rtnetlink::sys::{AsyncSocket, SocketAddr};
+use rtnetlink::Handle;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{Error, Result};
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+type Lookup = Pin<Box<dyn Future<Output = Result<[Option<IpAddr>; 2]>> + Send>>;
+
+/// Watches the preferred source address for reaching the internet, per
+/// address family.
+///
+/// The kernel is asked for the route to an address outside of the local
+/// networks whenever an address or route changes. An event is emitted for
+/// each family whose source address changed, starting with the initial ones.
+pub struct PrimaryWatcher<T> {
+    watcher: IfWatcher<T>,
+    conn: Connection<RtnlMessage, T>,
+    handle: Handle,
+    messages: UnboundedReceiver<(NetlinkMessage<RtnlMessage>, SocketAddr)>,
+    lookup: Option<Lookup>,
+    dirty: bool,
+    primary: [Option<IfAddress>; 2],
+    queue: VecDeque<PrimaryEvent>,
+}
+
+impl<T> std::fmt::Debug for PrimaryWatcher<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PrimaryWatcher")
+            .field("primary", &self.primary)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> PrimaryWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        let watcher = IfWatcher::new()?;
+        let (mut conn, handle, messages) = rtnetlink::new_connection_with_socket::<T>()?;
+        let addr = SocketAddr::new(0, RTMGRP_IPV4_ROUTE | RTMGRP_IPV6_ROUTE);
+        conn.socket_mut().socket_mut().bind(&addr)?;
+        Ok(Self {
+            watcher,
+            conn,
+            handle,
+            messages,
+            lookup: None,
+            dirty: true,
+            primary: Default::default(),
+            queue: Default::default(),
+        })
+    }
+
+    /// The current primary address of `family`.
+    pub fn primary(&self, family: IpFamily) -> Option<&IfAddress> {
+        self.primary[family as usize].as_ref()
+    }
+
+    fn update(&mut self, sources: [Option<IpAddr>; 2]) {
+        for (family, source) in [IpFamily::V4, IpFamily::V6].into_iter().zip(sources) {
+            let primary = &mut self.primary[family as usize];
+            let addrs = self.watcher.addresses();
+            if let Some(event) = crate::update_primary(primary, family, source, addrs) {
+                self.queue.push_back(event);
+            }
+        }
+    }
+
+    /// Poll for a change of the primary address.
+    pub fn poll_primary_event(&mut self, cx: &mut Context) -> Poll<Result<PrimaryEvent>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            if Pin::new(&mut self.conn).poll(cx).is_ready() {
+                return Poll::Ready(Err(socket_err()));
+            }
+            while let Poll::Ready(message) = self.messages.poll_next_unpin(cx) {
+                let (message, _) = message.ok_or_else(socket_err)?;
+                match message.payload {
+                    NetlinkPayload::Error(err) => return Poll::Ready(Err(err.to_io())),
+                    NetlinkPayload::InnerMessage(
+                        RtnlMessage::NewRoute(_) | RtnlMessage::DelRoute(_),
+                    ) => self.dirty = true,
+                    _ => {}
+                }
+            }
+            // Addresses are only looked up once the current ones are known.
+            if self.watcher.poll_synced(cx)?.is_pending() {
+                return Poll::Pending;
+            }
+            while let Poll::Ready(event) = self.watcher.poll_address_event(cx) {
+                event?;
+                self.dirty = true;
+            }
+            if let Some(lookup) = &mut self.lookup {
+                let sources = match lookup.as_mut().poll(cx) {
+                    Poll::Ready(sources) => sources?,
+                    Poll::Pending => return Poll::Pending,
+                };
+                self.lookup = None;
+                self.update(sources);
+                continue;
+            }
+            if !std::mem::take(&mut self.dirty) {
+                return Poll::Pending;
+            }
+            let handle = self.handle.clone();
+            self.lookup = Some(Box::pin(async move {
+                let v4 = lookup_source(handle.clone(), PROBE_V4.into()).await?;
+                let v6 = lookup_source(handle, PROBE_V6.into()).await?;
+                Ok([v4, v6])
+            }));
+        }
+    }
+}
+
+/// Asks the kernel for the preferred source address of the route to `dst`,
+/// like `ip route get`. Returns `None` if there is no such route.
+async fn lookup_source(mut handle: Handle, dst: IpAddr) -> Result<Option<IpAddr>> {
+    let mut msg = RouteMessage::default();
+    let (family, octets) = match dst {
+        IpAddr::V4(ip) => (2, ip.octets().to_vec()),
+        IpAddr::V6(ip) => (10, ip.octets().to_vec()),
+    };
+    msg.header.address_family = family;
+    msg.header.destination_prefix_length = octets.len() as u8 * 8;
+    msg.nlas.push(Nla::Destination(octets));
+    let mut req = NetlinkMessage::from(RtnlMessage::GetRoute(msg));
+    req.header.flags = NLM_F_REQUEST;
+    let mut response = handle.request(req).map_err(Error::other)?;
+    while let Some(msg) = response.next().await {
+        match msg.payload {
+            NetlinkPayload::InnerMessage(RtnlMessage::NewRoute(route)) => {
+                return Ok(route.nlas.iter().find_map(|nla| match nla {
+                    Nla::PrefSource(octets) => parse_ip(family, octets),
+                    _ => None,
+                }));
+            }
+            NetlinkPayload::Error(err) => {
+                let err = err.to_io();
+                return match err.raw_os_error() {
+                    Some(libc::ENETUNREACH | libc::EHOSTUNREACH) => Ok(None),
+                    _ => Err(err),
+                };
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+impl<T> Stream for PrimaryWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    type Item = Result<PrimaryEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_primary_event(cx).map(Some)
+    }
+}
+
+impl<T> FusedStream for PrimaryWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
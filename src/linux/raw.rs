@@ -0,0 +1,128 @@
+//! Encoding and parsing of netlink messages that `rtnetlink` does not
+//! support.
+use futures::future::poll_fn;
+use rtnetlink::sys::{AsyncSocket, SocketAddr};
+use std::io::{Error, Result};
+
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_ACK: u16 = 0x4;
+pub(super) const NLM_F_DUMP: u16 = 0x300;
+/// Attribute type bits that are not flags.
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+/// Sends a request with `payload` and returns the payloads of the replies,
+/// until the acknowledgement or the end of the dump.
+pub(super) async fn request<T: AsyncSocket>(
+    ctrl: &T,
+    kind: u16,
+    flags: u16,
+    payload: &[u8],
+) -> Result<Vec<Vec<u8>>> {
+    let msg = encode(kind, flags, payload);
+    poll_fn(|cx| ctrl.poll_send_to(cx, &msg, &SocketAddr::new(0, 0))).await?;
+    let mut replies = Vec::new();
+    loop {
+        let (buf, _) = poll_fn(|cx| ctrl.poll_recv_from_full(cx)).await?;
+        if let Some(result) = collect(&buf, &mut replies) {
+            return result.map(|()| replies);
+        }
+    }
+}
+
+/// Encodes a request with `payload`.
+fn encode(kind: u16, flags: u16, payload: &[u8]) -> Vec<u8> {
+    let len = NLMSG_HDRLEN + payload.len();
+    let mut msg = Vec::with_capacity(len);
+    msg.extend_from_slice(&(len as u32).to_ne_bytes());
+    msg.extend_from_slice(&kind.to_ne_bytes());
+    msg.extend_from_slice(&(NLM_F_REQUEST | NLM_F_ACK | flags).to_ne_bytes());
+    msg.extend_from_slice(&[0; 8]);
+    msg.extend_from_slice(payload);
+    msg
+}
+
+/// Adds the replies in `buf` to `replies`, returning the result of the
+/// request once the acknowledgement or the end of the dump has been received.
+fn collect(buf: &[u8], replies: &mut Vec<Vec<u8>>) -> Option<Result<()>> {
+    for (kind, payload) in messages(buf) {
+        match kind {
+            NLMSG_ERROR => {
+                let code = payload.get(..4).and_then(parse_u32).unwrap_or(0) as i32;
+                return Some(match code {
+                    0 => Ok(()),
+                    code => Err(Error::from_raw_os_error(-code)),
+                });
+            }
+            NLMSG_DONE => return Some(Ok(())),
+            // Replies are followed by the acknowledgement, or by the end of
+            // the dump if one was requested.
+            _ => replies.push(payload.to_vec()),
+        }
+    }
+    None
+}
+
+/// Iterates over the type and payload of the netlink messages in `buf`.
+pub(super) fn messages(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let len = buf.get(..4).and_then(parse_u32)? as usize;
+        if len < NLMSG_HDRLEN || len > buf.len() {
+            return None;
+        }
+        let kind = parse_u16(&buf[4..6])?;
+        let payload = &buf[NLMSG_HDRLEN..len];
+        buf = buf.get(align(len)..).unwrap_or_default();
+        Some((kind, payload))
+    })
+}
+
+/// Iterates over the type and payload of the attributes in `buf`.
+pub(super) fn attrs_iter(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let len = buf.get(..2).and_then(parse_u16)? as usize;
+        if len < 4 || len > buf.len() {
+            return None;
+        }
+        let kind = parse_u16(&buf[2..4])? & NLA_TYPE_MASK;
+        let payload = &buf[4..len];
+        buf = buf.get(align(len)..).unwrap_or_default();
+        Some((kind, payload))
+    })
+}
+
+pub(super) fn attr(buf: &[u8], kind: u16) -> Option<&[u8]> {
+    attrs_iter(buf).find_map(|(k, payload)| (k == kind).then_some(payload))
+}
+
+pub(super) fn put_attr(buf: &mut Vec<u8>, kind: u16, payload: &[u8]) {
+    let len = 4 + payload.len();
+    buf.extend_from_slice(&(len as u16).to_ne_bytes());
+    buf.extend_from_slice(&kind.to_ne_bytes());
+    buf.extend_from_slice(payload);
+    buf.resize(buf.len() + align(len) - len, 0);
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+pub(super) fn parse_u16(buf: &[u8]) -> Option<u16> {
+    Some(u16::from_ne_bytes(buf.try_into().ok()?))
+}
+
+pub(super) fn parse_u32(buf: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(buf.try_into().ok()?))
+}
+
+pub(super) fn index_to_name(if_index: u32) -> String {
+    let mut buf = [0u8; libc::IF_NAMESIZE];
+    let name = unsafe { libc::if_indextoname(if_index, buf.as_mut_ptr().cast()) };
+    if name.is_null() {
+        return String::new();
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
@@ -0,0 +1,323 @@
+//! Watching of the routing policy rules.
+use super::{message_stream, parse_ip, sequential_dump, socket_err, MessageStream};
+use crate::{IpFamily, IpNet};
+use futures::ready;
+use futures::stream::{FusedStream, Stream, TryStreamExt};
+use futures::StreamExt;
+use rtnetlink::constants::RTMGRP_IPV4_RULE;
+use rtnetlink::packet::constants::{
+    AF_INET, AF_INET6, FIB_RULE_INVERT, FR_ACT_BLACKHOLE, FR_ACT_GOTO, FR_ACT_NOP, FR_ACT_PROHIBIT,
+    FR_ACT_TO_TBL, FR_ACT_UNREACHABLE, RTNLGRP_IPV6_RULE,
+};
+use rtnetlink::packet::rule::Nla;
+use rtnetlink::packet::{RtnlMessage, RuleMessage};
+use rtnetlink::proto::Connection;
+use rtnetlink::sys::{AsyncSocket, SocketAddr};
+use rtnetlink::IpVersion;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::io::Result;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// What a rule does with matching packets, see `FR_ACT_*` in `ip-rule(8)`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum RuleAction {
+    /// Look up the route in the table with the given id.
+    Lookup(u32),
+    /// Continue with the rule of the given priority.
+    Goto(u32),
+    /// Do nothing.
+    Nop,
+    /// Silently drop the packet.
+    Blackhole,
+    /// Drop the packet with `ENETUNREACH`.
+    Unreachable,
+    /// Drop the packet with `EACCES`.
+    Prohibit,
+    /// Any other action.
+    Other(u8),
+}
+
+/// A routing policy rule.
+///
+/// Rules are evaluated in the order of their priority, which is also their
+/// sort order.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Rule {
+    /// Priority of the rule, lower values are evaluated first.
+    pub priority: u32,
+    /// Address family the rule applies to.
+    pub family: IpFamily,
+    /// Source prefix matched by the rule.
+    pub src: Option<IpNet>,
+    /// Destination prefix matched by the rule.
+    pub dst: Option<IpNet>,
+    /// Name of the interface packets are received on.
+    pub iif: Option<String>,
+    /// Name of the interface packets are sent on.
+    pub oif: Option<String>,
+    /// Firewall mark matched by the rule.
+    pub fwmark: Option<u32>,
+    /// Mask applied to the firewall mark before matching.
+    pub fwmask: Option<u32>,
+    /// Type of service matched by the rule, `0` matches any.
+    pub tos: u8,
+    /// Whether the rule applies to packets that do not match.
+    pub invert: bool,
+    /// Action of the rule.
+    pub action: RuleAction,
+}
+
+/// A routing policy rule change event.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum RuleEvent {
+    /// A rule has been added.
+    Added(Rule),
+    /// A rule has been removed.
+    Removed(Rule),
+}
+
+impl RuleEvent {
+    /// The rule that has been added or removed.
+    pub fn rule(&self) -> &Rule {
+        match self {
+            Self::Added(rule) | Self::Removed(rule) => rule,
+        }
+    }
+}
+
+/// Watches the IPv4 and IPv6 routing policy rules, see `ip-rule(8)`.
+///
+/// The current rules are reported as `Added` events first.
+pub struct RuleWatcher<T> {
+    conn: Connection<RtnlMessage, T>,
+    messages: MessageStream,
+    /// Rules and how many of them exist, since properties which are not
+    /// parsed may tell rules apart.
+    rules: BTreeMap<Rule, usize>,
+    queue: VecDeque<RuleEvent>,
+    synced: bool,
+}
+
+impl<T> std::fmt::Debug for RuleWatcher<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("RuleWatcher")
+            .field("rules", &self.rules)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> RuleWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        let (mut conn, handle, messages) = rtnetlink::new_connection_with_socket::<T>()?;
+        let socket = conn.socket_mut().socket_mut();
+        socket.bind(&SocketAddr::new(0, RTMGRP_IPV4_RULE))?;
+        // There is no legacy group for IPv6 rules.
+        socket.add_membership(RTNLGRP_IPV6_RULE)?;
+        let get_v4_rules_stream = handle
+            .rule()
+            .get(IpVersion::V4)
+            .execute()
+            .map_ok(RtnlMessage::NewRule);
+        let dump = sequential_dump(get_v4_rules_stream, move || {
+            handle
+                .rule()
+                .get(IpVersion::V6)
+                .execute()
+                .map_ok(RtnlMessage::NewRule)
+        });
+        let messages = message_stream(dump, messages);
+        Ok(Self {
+            conn,
+            messages,
+            rules: Default::default(),
+            queue: Default::default(),
+            synced: false,
+        })
+    }
+
+    /// Iterate over the current rules in the order of their priority.
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.keys()
+    }
+
+    fn add_rule(&mut self, msg: RuleMessage) {
+        let Some(rule) = parse_rule(msg) else {
+            return;
+        };
+        match self.rules.entry(rule.clone()) {
+            Entry::Vacant(entry) => {
+                entry.insert(1);
+                self.queue.push_back(RuleEvent::Added(rule));
+            }
+            Entry::Occupied(mut entry) => *entry.get_mut() += 1,
+        }
+    }
+
+    fn rem_rule(&mut self, msg: RuleMessage) {
+        let Some(rule) = parse_rule(msg) else {
+            return;
+        };
+        if let Entry::Occupied(mut entry) = self.rules.entry(rule) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                let (rule, _) = entry.remove_entry();
+                self.queue.push_back(RuleEvent::Removed(rule));
+            }
+        }
+    }
+
+    fn poll_message(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        if Pin::new(&mut self.conn).poll(cx).is_ready() {
+            return Poll::Ready(Err(socket_err()));
+        }
+        let message = ready!(self.messages.poll_next_unpin(cx)).ok_or_else(socket_err)??;
+        match message {
+            Some((RtnlMessage::NewRule(msg), _)) => self.add_rule(msg),
+            Some((RtnlMessage::DelRule(msg), _)) => self.rem_rule(msg),
+            Some(_) => {}
+            None => self.synced = true,
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Poll until the rules present when the watcher was created have been
+    /// loaded. Events for these rules remain queued.
+    pub fn poll_synced(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        while !self.synced {
+            ready!(self.poll_message(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Poll for a rule change event.
+    pub fn poll_rule_event(&mut self, cx: &mut Context) -> Poll<Result<RuleEvent>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            ready!(self.poll_message(cx))?;
+        }
+    }
+}
+
+impl<T> Stream for RuleWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    type Item = Result<RuleEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_rule_event(cx).map(Some)
+    }
+}
+
+impl<T> FusedStream for RuleWatcher<T>
+where
+    T: AsyncSocket + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
+
+/// Parses an IPv4 or IPv6 rule.
+fn parse_rule(msg: RuleMessage) -> Option<Rule> {
+    let header = msg.header;
+    let family = match header.family {
+        family if family == AF_INET as u8 => IpFamily::V4,
+        family if family == AF_INET6 as u8 => IpFamily::V6,
+        _ => return None,
+    };
+    let prefix = |octets: &[u8], len| IpNet::new(parse_ip(header.family, octets)?, len).ok();
+    let mut rule = Rule {
+        priority: 0,
+        family,
+        src: None,
+        dst: None,
+        iif: None,
+        oif: None,
+        fwmark: None,
+        fwmask: None,
+        tos: header.tos,
+        invert: header.flags & FIB_RULE_INVERT != 0,
+        action: RuleAction::Other(header.action),
+    };
+    let mut table = header.table.into();
+    let mut goto = 0;
+    for nla in msg.nlas {
+        match nla {
+            Nla::Priority(priority) => rule.priority = priority,
+            Nla::Source(octets) => rule.src = prefix(&octets, header.src_len),
+            Nla::Destination(octets) => rule.dst = prefix(&octets, header.dst_len),
+            Nla::Iifname(name) => rule.iif = Some(name),
+            Nla::OifName(name) => rule.oif = Some(name),
+            Nla::FwMark(mark) => rule.fwmark = Some(mark),
+            Nla::FwMask(mask) => rule.fwmask = Some(mask),
+            Nla::Table(id) => table = id,
+            Nla::Goto(priority) => goto = priority,
+            _ => {}
+        }
+    }
+    rule.action = match header.action {
+        FR_ACT_TO_TBL => RuleAction::Lookup(table),
+        FR_ACT_GOTO => RuleAction::Goto(goto),
+        FR_ACT_NOP => RuleAction::Nop,
+        FR_ACT_BLACKHOLE => RuleAction::Blackhole,
+        FR_ACT_UNREACHABLE => RuleAction::Unreachable,
+        FR_ACT_PROHIBIT => RuleAction::Prohibit,
+        action => RuleAction::Other(action),
+    };
+    Some(rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_rule, Rule, RuleAction};
+    use crate::IpFamily;
+    use rtnetlink::packet::constants::{AF_INET6, FIB_RULE_INVERT, FR_ACT_TO_TBL};
+    use rtnetlink::packet::rule::Nla;
+    use rtnetlink::packet::RuleMessage;
+
+    #[test]
+    fn test_parse_rule() {
+        // ip -6 rule add not from 2001:db8::/32 iif eth0 fwmark 1/3 table 1000 pref 100
+        let mut msg = RuleMessage::default();
+        msg.header.family = AF_INET6 as u8;
+        msg.header.src_len = 32;
+        msg.header.table = 252;
+        msg.header.action = FR_ACT_TO_TBL;
+        msg.header.flags = FIB_RULE_INVERT;
+        let mut src = vec![0x20, 0x01, 0x0d, 0xb8];
+        src.resize(16, 0);
+        msg.nlas = vec![
+            Nla::Table(1000),
+            Nla::Priority(100),
+            Nla::Source(src),
+            Nla::Iifname("eth0".into()),
+            Nla::FwMark(1),
+            Nla::FwMask(3),
+        ];
+        assert_eq!(
+            parse_rule(msg),
+            Some(Rule {
+                priority: 100,
+                family: IpFamily::V6,
+                src: Some("2001:db8::/32".parse().unwrap()),
+                dst: None,
+                iif: Some("eth0".into()),
+                oif: None,
+                fwmark: Some(1),
+                fwmask: Some(3),
+                tos: 0,
+                invert: true,
+                action: RuleAction::Lookup(1000),
+            })
+        );
+    }
+}
@@ -0,0 +1,432 @@
+//! Watching of Wi-Fi associations through `nl80211`.
+use super::raw::{
+    self, attr, attrs_iter, index_to_name, messages, parse_u16, parse_u32, put_attr, NLM_F_DUMP,
+};
+use fnv::FnvHashMap;
+use futures::ready;
+use futures::stream::{FusedStream, Stream};
+use rtnetlink::sys::protocols::NETLINK_GENERIC;
+use rtnetlink::sys::AsyncSocket;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{Error, ErrorKind, Result};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+const GENL_HDRLEN: usize = 4;
+
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+const CTRL_ATTR_MCAST_GROUPS: u16 = 7;
+const CTRL_ATTR_MCAST_GRP_NAME: u16 = 1;
+const CTRL_ATTR_MCAST_GRP_ID: u16 = 2;
+
+const NL80211_CMD_GET_INTERFACE: u8 = 5;
+const NL80211_CMD_GET_SCAN: u8 = 32;
+const NL80211_CMD_CONNECT: u8 = 46;
+const NL80211_CMD_ROAM: u8 = 47;
+const NL80211_CMD_DISCONNECT: u8 = 48;
+const NL80211_ATTR_IFINDEX: u16 = 3;
+const NL80211_ATTR_IFNAME: u16 = 4;
+const NL80211_ATTR_MAC: u16 = 6;
+const NL80211_ATTR_BSS: u16 = 47;
+const NL80211_ATTR_SSID: u16 = 52;
+const NL80211_ATTR_STATUS_CODE: u16 = 72;
+const NL80211_ATTR_REQ_IE: u16 = 77;
+const NL80211_BSS_BSSID: u16 = 1;
+const NL80211_BSS_INFORMATION_ELEMENTS: u16 = 6;
+const NL80211_BSS_STATUS: u16 = 9;
+const NL80211_BSS_STATUS_ASSOCIATED: u32 = 1;
+/// Element id of the SSID in information elements.
+const WLAN_EID_SSID: u8 = 0;
+
+/// The association of a wireless interface with an access point.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct WifiLink {
+    /// Index of the interface, as in the events of the address watcher.
+    pub if_index: u32,
+    /// Name of the interface.
+    pub if_name: String,
+    /// Name of the network, which is not necessarily UTF-8. `None` if the
+    /// kernel did not report it.
+    pub ssid: Option<Vec<u8>>,
+    /// Hardware address of the access point.
+    pub bssid: Option<Vec<u8>>,
+}
+
+/// A Wi-Fi association change event.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum WifiEvent {
+    /// An interface has connected to a network.
+    Connected(WifiLink),
+    /// An interface has moved to another access point, usually of the same
+    /// network, without disconnecting.
+    Roamed {
+        /// The association before roaming.
+        old: WifiLink,
+        /// The association after roaming.
+        new: WifiLink,
+    },
+    /// An interface has disconnected from a network.
+    Disconnected(WifiLink),
+}
+
+impl WifiEvent {
+    /// The current association, or the last one if the interface has
+    /// disconnected.
+    pub fn link(&self) -> &WifiLink {
+        match self {
+            Self::Connected(link) | Self::Roamed { new: link, .. } | Self::Disconnected(link) => {
+                link
+            }
+        }
+    }
+}
+
+/// Watches the associations of wireless interfaces with access points through
+/// the `mlme` multicast group of `nl80211`.
+///
+/// The current associations are reported as `Connected` events first.
+/// Polling the watcher fails with [`ErrorKind::Unsupported`] if the kernel
+/// has no `nl80211` support, e.g. because there is no wireless driver.
+pub struct WifiWatcher<T> {
+    state: State<T>,
+    associations: Associations,
+}
+
+/// Resolves the family, joins its `mlme` group on the socket and loads the
+/// current associations.
+type Load<T> = Pin<Box<dyn Future<Output = Result<(T, u16, Vec<WifiLink>)>> + Send>>;
+
+enum State<T> {
+    Loading(Load<T>),
+    /// The socket receiving notifications and the id of the family.
+    Loaded(T, u16),
+    /// Loading failed with an error of this kind.
+    Failed(ErrorKind),
+}
+
+impl<T> std::fmt::Debug for WifiWatcher<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("WifiWatcher")
+            .field("links", &self.associations.links)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> WifiWatcher<T>
+where
+    T: AsyncSocket + Unpin + Send + Sync + 'static,
+{
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        // The requests for the family and the current associations are made
+        // on a separate socket.
+        let mut ctrl = T::new(NETLINK_GENERIC)?;
+        ctrl.socket_mut().bind_auto()?;
+        let mut socket = T::new(NETLINK_GENERIC)?;
+        socket.socket_mut().bind_auto()?;
+        Ok(Self {
+            state: State::Loading(Box::pin(load(ctrl, socket))),
+            associations: Default::default(),
+        })
+    }
+
+    /// Iterate over the current associations.
+    pub fn links(&self) -> impl Iterator<Item = &WifiLink> {
+        self.associations.links.values()
+    }
+
+    /// Poll for an association change event.
+    pub fn poll_wifi_event(&mut self, cx: &mut Context) -> Poll<Result<WifiEvent>> {
+        loop {
+            if let Some(event) = self.associations.queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            let (socket, family) = match &mut self.state {
+                State::Loading(load) => {
+                    match ready!(load.as_mut().poll(cx)) {
+                        Ok((socket, family, links)) => {
+                            for link in links {
+                                self.associations.insert(link);
+                            }
+                            self.state = State::Loaded(socket, family);
+                        }
+                        Err(err) => {
+                            self.state = State::Failed(err.kind());
+                            return Poll::Ready(Err(err));
+                        }
+                    }
+                    continue;
+                }
+                State::Loaded(socket, family) => (socket, *family),
+                State::Failed(kind) => return Poll::Ready(Err((*kind).into())),
+            };
+            let result = ready!(socket.poll_recv_from_full(cx));
+            #[cfg(feature = "metrics")]
+            if let Err(err) = &result {
+                crate::stats::recv_error(super::BACKEND, err);
+            }
+            let (buf, _) = result?;
+            for (kind, payload) in messages(&buf) {
+                if kind == family && payload.len() >= GENL_HDRLEN {
+                    self.associations
+                        .handle(payload[0], &payload[GENL_HDRLEN..]);
+                }
+            }
+        }
+    }
+}
+
+/// The current associations and the events not yet polled.
+#[derive(Debug, Default)]
+struct Associations {
+    links: FnvHashMap<u32, WifiLink>,
+    queue: VecDeque<WifiEvent>,
+}
+
+impl Associations {
+    fn insert(&mut self, link: WifiLink) {
+        self.links.insert(link.if_index, link.clone());
+        self.queue.push_back(WifiEvent::Connected(link));
+    }
+
+    /// Handles an `nl80211` notification with the command `cmd`.
+    fn handle(&mut self, cmd: u8, attrs: &[u8]) {
+        let Some(if_index) = attr(attrs, NL80211_ATTR_IFINDEX).and_then(parse_u32) else {
+            return;
+        };
+        match cmd {
+            NL80211_CMD_CONNECT | NL80211_CMD_ROAM => {
+                // Failed connection attempts have a non-zero status code.
+                let status = attr(attrs, NL80211_ATTR_STATUS_CODE).and_then(parse_u16);
+                if status.is_some_and(|status| status != 0) {
+                    return;
+                }
+                let old = self.links.get(&if_index);
+                let mut new = WifiLink {
+                    if_index,
+                    if_name: old
+                        .map(|link| link.if_name.clone())
+                        .unwrap_or_else(|| index_to_name(if_index)),
+                    ssid: attr(attrs, NL80211_ATTR_REQ_IE).and_then(find_ssid),
+                    bssid: attr(attrs, NL80211_ATTR_MAC).map(<[u8]>::to_vec),
+                };
+                // Roaming notifications do not always include the SSID.
+                if let Some(old) = old.filter(|_| new.ssid.is_none()) {
+                    new.ssid.clone_from(&old.ssid);
+                }
+                let event = match old {
+                    None => WifiEvent::Connected(new.clone()),
+                    Some(old) if *old == new => return,
+                    Some(old) => WifiEvent::Roamed {
+                        old: old.clone(),
+                        new: new.clone(),
+                    },
+                };
+                self.links.insert(if_index, new);
+                self.queue.push_back(event);
+            }
+            NL80211_CMD_DISCONNECT => {
+                if let Some(link) = self.links.remove(&if_index) {
+                    self.queue.push_back(WifiEvent::Disconnected(link));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn load<T: AsyncSocket>(ctrl: T, mut socket: T) -> Result<(T, u16, Vec<WifiLink>)> {
+    let (family, mlme) = resolve_family(&ctrl).await?;
+    // Join the group before loading the current associations so that no
+    // change is missed.
+    socket.socket_mut().add_membership(mlme)?;
+    let links = dump_links(&ctrl, family).await?;
+    Ok((socket, family, links))
+}
+
+/// Looks up the id and the id of the `mlme` multicast group of the `nl80211`
+/// generic netlink family.
+async fn resolve_family<T: AsyncSocket>(ctrl: &T) -> Result<(u16, u32)> {
+    let mut attrs = Vec::new();
+    put_attr(&mut attrs, CTRL_ATTR_FAMILY_NAME, b"nl80211\0");
+    let replies = match request(ctrl, GENL_ID_CTRL, CTRL_CMD_GETFAMILY, 0, &attrs).await {
+        Err(err) if err.raw_os_error() == Some(libc::ENOENT) => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "nl80211 is not available",
+            ));
+        }
+        replies => replies?,
+    };
+    let reply = replies.first().map(Vec::as_slice).unwrap_or_default();
+    let family = attr(reply, CTRL_ATTR_FAMILY_ID).and_then(parse_u16);
+    let mlme = attr(reply, CTRL_ATTR_MCAST_GROUPS).and_then(|groups| {
+        attrs_iter(groups).find_map(|(_, group)| {
+            let name = attr(group, CTRL_ATTR_MCAST_GRP_NAME)?;
+            (name == b"mlme\0")
+                .then(|| attr(group, CTRL_ATTR_MCAST_GRP_ID).and_then(parse_u32))
+                .flatten()
+        })
+    });
+    match (family, mlme) {
+        (Some(family), Some(mlme)) => Ok((family, mlme)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "incomplete nl80211 family description",
+        )),
+    }
+}
+
+/// Loads the current associations of all wireless interfaces.
+async fn dump_links<T: AsyncSocket>(ctrl: &T, family: u16) -> Result<Vec<WifiLink>> {
+    let mut links = Vec::new();
+    for iface in request(ctrl, family, NL80211_CMD_GET_INTERFACE, NLM_F_DUMP, &[]).await? {
+        let Some(if_index) = attr(&iface, NL80211_ATTR_IFINDEX).and_then(parse_u32) else {
+            continue;
+        };
+        let mut attrs = Vec::new();
+        put_attr(&mut attrs, NL80211_ATTR_IFINDEX, &if_index.to_ne_bytes());
+        let bss = request(ctrl, family, NL80211_CMD_GET_SCAN, NLM_F_DUMP, &attrs)
+            .await?
+            .into_iter()
+            .filter_map(|scan| attr(&scan, NL80211_ATTR_BSS).map(<[u8]>::to_vec))
+            .find(|bss| {
+                attr(bss, NL80211_BSS_STATUS).and_then(parse_u32)
+                    == Some(NL80211_BSS_STATUS_ASSOCIATED)
+            });
+        let ssid = attr(&iface, NL80211_ATTR_SSID).map(<[u8]>::to_vec);
+        if ssid.is_none() && bss.is_none() {
+            continue;
+        }
+        let bss = bss.unwrap_or_default();
+        links.push(WifiLink {
+            if_index,
+            if_name: attr(&iface, NL80211_ATTR_IFNAME)
+                .map(|name| String::from_utf8_lossy(name.strip_suffix(b"\0").unwrap_or(name)))
+                .map(Into::into)
+                .unwrap_or_else(|| index_to_name(if_index)),
+            ssid: ssid.or_else(|| attr(&bss, NL80211_BSS_INFORMATION_ELEMENTS).and_then(find_ssid)),
+            bssid: attr(&bss, NL80211_BSS_BSSID).map(<[u8]>::to_vec),
+        });
+    }
+    Ok(links)
+}
+
+/// Sends a generic netlink request and returns the attributes of the replies.
+async fn request<T: AsyncSocket>(
+    ctrl: &T,
+    family: u16,
+    cmd: u8,
+    flags: u16,
+    attrs: &[u8],
+) -> Result<Vec<Vec<u8>>> {
+    let mut payload = vec![cmd, 1, 0, 0];
+    payload.extend_from_slice(attrs);
+    let replies = raw::request(ctrl, family, flags, &payload).await?;
+    Ok(replies
+        .into_iter()
+        .map(|reply| reply.get(GENL_HDRLEN..).unwrap_or_default().to_vec())
+        .collect())
+}
+
+/// Finds the SSID in the information elements `ies`.
+fn find_ssid(mut ies: &[u8]) -> Option<Vec<u8>> {
+    while let [id, len, rest @ ..] = ies {
+        let data = rest.get(..*len as usize)?;
+        if *id == WLAN_EID_SSID {
+            return Some(data.to_vec());
+        }
+        ies = &rest[*len as usize..];
+    }
+    None
+}
+
+impl<T> Stream for WifiWatcher<T>
+where
+    T: AsyncSocket + Unpin + Send + Sync + 'static,
+{
+    type Item = Result<WifiEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_wifi_event(cx).map(Some)
+    }
+}
+
+impl<T> FusedStream for WifiWatcher<T>
+where
+    T: AsyncSocket + Unpin + Send + Sync + 'static,
+{
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::raw::put_attr;
+    use super::{
+        Associations, WifiEvent, WifiLink, NL80211_ATTR_IFINDEX, NL80211_ATTR_MAC,
+        NL80211_ATTR_REQ_IE, NL80211_ATTR_STATUS_CODE, NL80211_CMD_CONNECT, NL80211_CMD_DISCONNECT,
+        NL80211_CMD_ROAM, WLAN_EID_SSID,
+    };
+
+    fn notification(if_index: u32, bssid: Option<[u8; 6]>, ssid: Option<&[u8]>) -> Vec<u8> {
+        let mut attrs = Vec::new();
+        put_attr(&mut attrs, NL80211_ATTR_IFINDEX, &if_index.to_ne_bytes());
+        if let Some(bssid) = bssid {
+            put_attr(&mut attrs, NL80211_ATTR_MAC, &bssid);
+        }
+        if let Some(ssid) = ssid {
+            // Supported rates followed by the SSID.
+            let mut ies = vec![1, 2, 0x82, 0x84, WLAN_EID_SSID, ssid.len() as u8];
+            ies.extend_from_slice(ssid);
+            put_attr(&mut attrs, NL80211_ATTR_REQ_IE, &ies);
+        }
+        attrs
+    }
+
+    #[test]
+    fn test_associations() {
+        let mut assoc = Associations::default();
+        let ap1 = [2, 0, 0, 0, 0, 1];
+        let ap2 = [2, 0, 0, 0, 0, 2];
+        assoc.handle(
+            NL80211_CMD_CONNECT,
+            &notification(1000, Some(ap1), Some(b"home")),
+        );
+        assoc.handle(NL80211_CMD_ROAM, &notification(1000, Some(ap2), None));
+        // A connect notification for the current association is ignored.
+        assoc.handle(
+            NL80211_CMD_CONNECT,
+            &notification(1000, Some(ap2), Some(b"home")),
+        );
+        assoc.handle(NL80211_CMD_DISCONNECT, &notification(1000, None, None));
+        // Failed connection attempts are ignored.
+        let mut failed = notification(1000, Some(ap1), Some(b"home"));
+        put_attr(&mut failed, NL80211_ATTR_STATUS_CODE, &1u16.to_ne_bytes());
+        assoc.handle(NL80211_CMD_CONNECT, &failed);
+
+        let link = |bssid: [u8; 6]| WifiLink {
+            if_index: 1000,
+            if_name: String::new(),
+            ssid: Some(b"home".to_vec()),
+            bssid: Some(bssid.to_vec()),
+        };
+        let events: Vec<_> = assoc.queue.drain(..).collect();
+        assert_eq!(
+            events,
+            [
+                WifiEvent::Connected(link(ap1)),
+                WifiEvent::Roamed {
+                    old: link(ap1),
+                    new: link(ap2),
+                },
+                WifiEvent::Disconnected(link(ap2)),
+            ]
+        );
+        assert!(assoc.links.is_empty());
+    }
+}
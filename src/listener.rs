@@ -0,0 +1,326 @@
+//! Binding of a socket to every local address.
+use crate::{IfAddress, IfAddressEvent};
+use futures::stream::{FusedStream, Stream};
+use futures::FutureExt;
+use futures_timer::Delay;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Delay between attempts to bind to an address that is not available yet.
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+/// Number of attempts to bind to an address that is not available yet, which
+/// covers the duplicate address detection of IPv6 addresses.
+const MAX_ATTEMPTS: u32 = 20;
+
+/// A change of the sockets of a [`ListenerManager`].
+#[derive(Debug)]
+pub enum ListenerEvent {
+    /// A socket has been bound to a new address.
+    Bound(SocketAddr),
+    /// The address of a socket has gone away and the socket has been closed.
+    Closed(SocketAddr),
+    /// No socket could be bound to a new address.
+    BindFailed {
+        /// The address.
+        addr: SocketAddr,
+        /// The error of the last attempt.
+        error: Error,
+    },
+}
+
+/// A source of address events, implemented by the `IfWatcher` of every
+/// platform.
+pub trait AddressWatcher {
+    /// Poll for an address change event including the interface of the
+    /// address.
+    fn poll_address_event(&mut self, cx: &mut Context) -> Poll<Result<IfAddressEvent>>;
+}
+
+type Factory<S> = Box<dyn FnMut(SocketAddr) -> Result<S> + Send>;
+
+/// Maintains a socket bound to every address reported by a watcher.
+///
+/// Sockets are created by a factory, such as `UdpSocket::bind` or
+/// `TcpListener::bind`, for the port given to the manager and the address
+/// that came up, and are dropped when the address goes away. Binding to an
+/// IPv6 address fails while duplicate address detection is in progress, so
+/// it is retried for a few seconds before `BindFailed` is reported. IPv6
+/// link-local addresses are bound with the index of their interface as the
+/// scope id, and skipped if it is unknown.
+///
+/// The addresses are selected with `IfWatcher::set_filter`, through
+/// [`ListenerManager::watcher_mut`] once the manager has been created.
+pub struct ListenerManager<W, S> {
+    watcher: W,
+    port: u16,
+    factory: Factory<S>,
+    listeners: BTreeMap<SocketAddr, S>,
+    /// Addresses that are not available yet, the number of attempts and the
+    /// time of the next attempt.
+    pending: BTreeMap<SocketAddr, (u32, Instant)>,
+    /// The time the timer is set to.
+    wakeup: Option<Instant>,
+    /// Wakes up the task to retry.
+    timer: Option<Delay>,
+    queue: VecDeque<ListenerEvent>,
+}
+
+impl<W, S> std::fmt::Debug for ListenerManager<W, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ListenerManager")
+            .field("port", &self.port)
+            .field("listeners", &self.listeners.keys())
+            .field("pending", &self.pending.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<W, S> ListenerManager<W, S>
+where
+    W: AddressWatcher,
+{
+    /// Create a manager binding sockets created by `factory` to `port` on the
+    /// addresses of `watcher`.
+    pub fn new<F>(watcher: W, port: u16, factory: F) -> Self
+    where
+        F: FnMut(SocketAddr) -> Result<S> + Send + 'static,
+    {
+        Self {
+            watcher,
+            port,
+            factory: Box::new(factory),
+            listeners: Default::default(),
+            pending: Default::default(),
+            wakeup: None,
+            timer: None,
+            queue: Default::default(),
+        }
+    }
+
+    /// The socket bound to `addr`, whose scope id is the interface index for
+    /// IPv6 link-local addresses.
+    pub fn listener(&self, addr: SocketAddr) -> Option<&S> {
+        self.listeners.get(&addr)
+    }
+
+    /// Iterate over the addresses and the sockets bound to them.
+    pub fn listeners(&self) -> impl Iterator<Item = (SocketAddr, &S)> {
+        self.listeners.iter().map(|(addr, socket)| (*addr, socket))
+    }
+
+    /// The watcher the addresses are taken from.
+    pub fn watcher(&self) -> &W {
+        &self.watcher
+    }
+
+    /// The watcher the addresses are taken from, for changing its filter.
+    pub fn watcher_mut(&mut self) -> &mut W {
+        &mut self.watcher
+    }
+
+    /// The address to bind to for `addr`, which is scoped to its interface
+    /// if it is an IPv6 link-local address.
+    fn socket_addr(&self, addr: &IfAddress) -> Option<SocketAddr> {
+        match addr.net.addr() {
+            IpAddr::V6(ip) if crate::is_link_local_v6(&ip) => {
+                Some(SocketAddrV6::new(ip, self.port, 0, addr.if_index?).into())
+            }
+            ip => Some(SocketAddr::new(ip, self.port)),
+        }
+    }
+
+    fn bind(&mut self, addr: SocketAddr, attempts: u32) {
+        match (self.factory)(addr) {
+            Ok(socket) => {
+                self.listeners.insert(addr, socket);
+                self.queue.push_back(ListenerEvent::Bound(addr));
+            }
+            // The address is still tentative.
+            Err(error)
+                if error.kind() == ErrorKind::AddrNotAvailable
+                    && addr.is_ipv6()
+                    && attempts + 1 < MAX_ATTEMPTS =>
+            {
+                let retry = Instant::now() + RETRY_DELAY;
+                self.pending.insert(addr, (attempts + 1, retry));
+            }
+            Err(error) => self
+                .queue
+                .push_back(ListenerEvent::BindFailed { addr, error }),
+        }
+    }
+
+    fn handle(&mut self, event: IfAddressEvent) {
+        let Some(addr) = self.socket_addr(event.address()) else {
+            return;
+        };
+        match event {
+            IfAddressEvent::Up(_) => {
+                if !self.listeners.contains_key(&addr) {
+                    self.pending.remove(&addr);
+                    self.bind(addr, 0);
+                }
+            }
+            IfAddressEvent::Down(_) => {
+                self.pending.remove(&addr);
+                if self.listeners.remove(&addr).is_some() {
+                    self.queue.push_back(ListenerEvent::Closed(addr));
+                }
+            }
+            IfAddressEvent::Changed { .. } => {}
+        }
+    }
+
+    /// Retries the pending addresses that are due and sets the timer to the
+    /// next one.
+    fn retry(&mut self) {
+        let now = Instant::now();
+        let due: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, retry))| *retry <= now)
+            .map(|(addr, (attempts, _))| (*addr, *attempts))
+            .collect();
+        for (addr, attempts) in due {
+            self.pending.remove(&addr);
+            self.bind(addr, attempts);
+        }
+        let next = self.pending.values().map(|(_, retry)| *retry).min();
+        if next != self.wakeup {
+            self.timer = next.map(|next| Delay::new(next.saturating_duration_since(now)));
+            self.wakeup = next;
+        }
+    }
+
+    /// Poll for a change of the sockets.
+    pub fn poll_listener_event(&mut self, cx: &mut Context) -> Poll<Result<ListenerEvent>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            if let Poll::Ready(event) = self.watcher.poll_address_event(cx) {
+                self.handle(event?);
+                continue;
+            }
+            self.retry();
+            if !self.queue.is_empty() {
+                continue;
+            }
+            match self.timer.as_mut().map(|timer| timer.poll_unpin(cx)) {
+                Some(Poll::Ready(())) => {
+                    self.timer = None;
+                    self.wakeup = None;
+                }
+                _ => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<W, S> Stream for ListenerManager<W, S>
+where
+    W: AddressWatcher + Unpin,
+    S: Unpin,
+{
+    type Item = Result<ListenerEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_listener_event(cx).map(Some)
+    }
+}
+
+impl<W, S> FusedStream for ListenerManager<W, S>
+where
+    W: AddressWatcher + Unpin,
+    S: Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressWatcher, ListenerEvent, ListenerManager};
+    use crate::{IfAddress, IfAddressEvent};
+    use futures::StreamExt;
+    use std::collections::VecDeque;
+    use std::io::{Error, ErrorKind, Result};
+    use std::net::{SocketAddr, UdpSocket};
+    use std::task::{Context, Poll};
+
+    /// A watcher returning queued events.
+    struct Events(VecDeque<IfAddressEvent>);
+
+    impl AddressWatcher for Events {
+        fn poll_address_event(&mut self, _: &mut Context) -> Poll<Result<IfAddressEvent>> {
+            match self.0.pop_front() {
+                Some(event) => Poll::Ready(Ok(event)),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    #[test]
+    fn test_listener_manager() {
+        let events = [
+            IfAddressEvent::Up(IfAddress::test("127.0.0.1/8", 1, "lo")),
+            IfAddressEvent::Up(IfAddress {
+                if_index: None,
+                ..IfAddress::test("fe80::1/64", 0, "lo")
+            }),
+            IfAddressEvent::Up(IfAddress::test("fe80::1/64", 2, "lo")),
+            IfAddressEvent::Up(IfAddress::test("::1/128", 1, "lo")),
+            IfAddressEvent::Up(IfAddress::test("127.0.0.2/8", 1, "lo")),
+            IfAddressEvent::Down(IfAddress::test("127.0.0.1/8", 1, "lo")),
+        ];
+        let watcher = Events(events.into());
+        // Binding to `::1` fails twice as if it was tentative, and `127.0.0.2`
+        // is rejected.
+        let mut attempts = 0;
+        let mut manager = ListenerManager::new(watcher, 0, move |addr: SocketAddr| {
+            match addr.ip().to_string().as_str() {
+                "::1" if attempts < 2 => {
+                    attempts += 1;
+                    Err(ErrorKind::AddrNotAvailable.into())
+                }
+                "127.0.0.2" => Err(Error::other("rejected")),
+                // The link-local address is not assigned to any interface.
+                "fe80::1" => UdpSocket::bind("[::1]:0"),
+                _ => UdpSocket::bind(addr),
+            }
+        });
+        let events: Vec<_> = smol::block_on(async {
+            let mut events = Vec::new();
+            for _ in 0..5 {
+                let event = match manager.next().await.unwrap().unwrap() {
+                    ListenerEvent::Bound(addr) => format!("bound {}", addr),
+                    ListenerEvent::Closed(addr) => format!("closed {}", addr),
+                    ListenerEvent::BindFailed { addr, error } => {
+                        format!("failed {} {}", addr, error)
+                    }
+                };
+                events.push(event);
+            }
+            events
+        });
+        assert_eq!(
+            events,
+            [
+                "bound 127.0.0.1:0",
+                "bound [fe80::1%2]:0",
+                "failed 127.0.0.2:0 rejected",
+                "closed 127.0.0.1:0",
+                "bound [::1]:0",
+            ]
+        );
+        let listeners: Vec<_> = manager.listeners().map(|(addr, _)| addr).collect();
+        assert_eq!(
+            listeners,
+            ["[::1]:0", "[fe80::1%2]:0"].map(|addr| addr.parse::<SocketAddr>().unwrap())
+        );
+    }
+}
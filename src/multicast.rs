@@ -0,0 +1,159 @@
+//! Joining of multicast groups on new interfaces.
+use crate::{IfAddressEvent, IfFlags};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, UdpSocket};
+
+/// Keeps a UDP socket joined to a multicast group on every interface with an
+/// address of the group's family.
+///
+/// Memberships are tied to interfaces, so they are lost when an interface is
+/// removed, and an interface that is created again has to join the group
+/// again. The joiner is fed the address events of a watcher and joins the
+/// group whenever an address comes up, which also covers interfaces that are
+/// renumbered or recreated. Loopback interfaces and interfaces without
+/// multicast support are skipped. Use `IfWatcher::set_filter` to select the
+/// interfaces.
+#[derive(Debug)]
+pub struct MulticastJoiner {
+    socket: UdpSocket,
+    group: IpAddr,
+}
+
+impl MulticastJoiner {
+    /// Create a joiner for `group` on `socket`, which should be bound to the
+    /// port of the group and the unspecified address.
+    pub fn new(socket: UdpSocket, group: IpAddr) -> Result<Self> {
+        if !group.is_multicast() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "not a multicast address",
+            ));
+        }
+        Ok(Self { socket, group })
+    }
+
+    /// The socket that joins the group.
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Consumes the joiner, returning the socket.
+    pub fn into_socket(self) -> UdpSocket {
+        self.socket
+    }
+
+    /// The group that is joined.
+    pub fn group(&self) -> IpAddr {
+        self.group
+    }
+
+    /// Join the group on the interface of an address that came up.
+    ///
+    /// `flags` are the flags of the interface, see `IfWatcher::link_flags`.
+    /// The group is not joined on loopback interfaces or interfaces without
+    /// multicast support. Without flags, which are only available on Linux,
+    /// loopback interfaces are recognized by their addresses.
+    ///
+    /// Returns whether the group has been joined, which is not the case for
+    /// addresses going down or changing, addresses of the other family,
+    /// skipped interfaces and interfaces on which the group has already been
+    /// joined. IPv6 groups are joined by interface index, so they are not
+    /// joined on interfaces without one.
+    pub fn handle(&mut self, event: &IfAddressEvent, flags: Option<IfFlags>) -> Result<bool> {
+        let IfAddressEvent::Up(addr) = event else {
+            return Ok(false);
+        };
+        let supported = match flags {
+            Some(flags) => flags.multicast && !flags.loopback,
+            None => !addr.net.addr().is_loopback(),
+        };
+        if !supported {
+            return Ok(false);
+        }
+        let joined = match (self.group, addr.net.addr()) {
+            (IpAddr::V4(group), IpAddr::V4(ip)) => self.socket.join_multicast_v4(&group, &ip),
+            (IpAddr::V6(group), IpAddr::V6(_)) => match addr.if_index {
+                Some(index) => self.socket.join_multicast_v6(&group, index),
+                None => return Ok(false),
+            },
+            _ => return Ok(false),
+        };
+        match joined {
+            Ok(()) => Ok(true),
+            // The group is joined once per interface, not per address.
+            Err(err) if err.kind() == ErrorKind::AddrInUse => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MulticastJoiner;
+    use crate::{IfAddress, IfAddressEvent, IfFlags};
+    use std::net::UdpSocket;
+
+    fn event(net: &str, if_index: u32) -> IfAddressEvent {
+        IfAddressEvent::Up(IfAddress::test(net, if_index, "lo"))
+    }
+
+    const MULTICAST: IfFlags = IfFlags {
+        up: true,
+        running: true,
+        loopback: false,
+        multicast: true,
+        point_to_point: false,
+    };
+
+    #[test]
+    fn test_multicast_joiner() {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        assert!(MulticastJoiner::new(socket, "10.0.0.1".parse().unwrap()).is_err());
+
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut joiner = MulticastJoiner::new(socket, "239.255.70.77".parse().unwrap()).unwrap();
+        // Pass `lo` off as a multicast interface so that the group is joined.
+        assert!(joiner
+            .handle(&event("127.0.0.1/8", 1), Some(MULTICAST))
+            .unwrap());
+        // Another address on the same interface.
+        assert!(!joiner
+            .handle(&event("127.0.0.2/8", 1), Some(MULTICAST))
+            .unwrap());
+        assert!(!joiner
+            .handle(&event("::1/128", 1), Some(MULTICAST))
+            .unwrap());
+        let IfAddressEvent::Up(addr) = event("127.0.0.1/8", 1) else {
+            unreachable!()
+        };
+        assert!(!joiner
+            .handle(&IfAddressEvent::Down(addr), Some(MULTICAST))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_multicast_joiner_skips() {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut joiner = MulticastJoiner::new(socket, "239.255.70.78".parse().unwrap()).unwrap();
+        let wireguard = IfFlags {
+            multicast: false,
+            point_to_point: true,
+            ..MULTICAST
+        };
+        let loopback = IfFlags {
+            loopback: true,
+            ..MULTICAST
+        };
+        assert!(!joiner
+            .handle(&event("127.0.0.1/8", 1), Some(wireguard))
+            .unwrap());
+        assert!(!joiner
+            .handle(&event("127.0.0.1/8", 1), Some(loopback))
+            .unwrap());
+        assert!(!joiner.handle(&event("127.0.0.1/8", 1), None).unwrap());
+        // Nothing has been joined yet.
+        assert!(joiner
+            .handle(&event("127.0.0.1/8", 1), Some(MULTICAST))
+            .unwrap());
+    }
+}
@@ -0,0 +1,284 @@
+//! Grouping of address changes by prefix.
+use crate::{IfAddressEvent, IpNet, PrefixEvent};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Index and name of an interface.
+type Interface = (Option<u32>, String);
+
+/// The addresses of the prefixes of an interface and whether they are
+/// deprecated.
+type Prefixes = BTreeMap<IpNet, BTreeMap<IpNet, bool>>;
+
+/// Groups address changes by prefix and detects renumbering.
+///
+/// When a delegated prefix changes, every address of the old prefix is
+/// deleted and an address of the new prefix is added. Instead of unrelated
+/// `Down` and `Up` events, the tracker reports that the prefix has been
+/// renumbered, so that services can update their announcements at once.
+///
+/// IPv6 prefixes are usually not removed right away. The addresses of the
+/// old prefix are deprecated when the new one is announced and only removed
+/// once their valid lifetime expires, possibly hours later. A prefix whose
+/// addresses are all deprecated is therefore reported as removed, and not
+/// reported again when its addresses are eventually deleted.
+///
+/// The tracker does not watch anything by itself. It is fed the address
+/// events of a watcher in batches, and a prefix removed or deprecated and
+/// another one added in the same batch on the same interface are paired up
+/// if they have the same address family and prefix length. Useful batches
+/// are all events that are ready at once, i.e. until
+/// `IfWatcher::poll_address_event` returns `Pending`, or the events received
+/// within a debounce window.
+#[derive(Clone, Debug, Default)]
+pub struct PrefixTracker {
+    prefixes: BTreeMap<Interface, Prefixes>,
+}
+
+impl PrefixTracker {
+    /// Create a tracker without any prefixes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Iterate over the current prefixes, which are not deprecated, and the
+    /// index and name of their interface.
+    pub fn prefixes(&self) -> impl Iterator<Item = (Option<u32>, &str, IpNet)> {
+        self.prefixes.iter().flat_map(|((index, name), prefixes)| {
+            current(prefixes)
+                .into_iter()
+                .map(move |prefix| (*index, name.as_str(), prefix))
+        })
+    }
+
+    /// Apply a batch of address events and return the resulting prefix
+    /// changes, sorted by interface.
+    pub fn update<'a, I>(&mut self, events: I) -> Vec<PrefixEvent>
+    where
+        I: IntoIterator<Item = &'a IfAddressEvent>,
+    {
+        let mut before = BTreeMap::new();
+        for event in events {
+            let addr = event.address();
+            let iface = (addr.if_index, addr.if_name.clone());
+            let prefixes = self.prefixes.entry(iface.clone()).or_default();
+            before.entry(iface).or_insert_with(|| current(prefixes));
+            let prefix = addr.net.trunc();
+            match event {
+                IfAddressEvent::Up(_) | IfAddressEvent::Changed { .. } => {
+                    let deprecated = addr.flags.is_some_and(|flags| flags.deprecated);
+                    prefixes
+                        .entry(prefix)
+                        .or_default()
+                        .insert(addr.net, deprecated);
+                }
+                IfAddressEvent::Down(_) => {
+                    if let Some(addrs) = prefixes.get_mut(&prefix) {
+                        addrs.remove(&addr.net);
+                        if addrs.is_empty() {
+                            prefixes.remove(&prefix);
+                        }
+                    }
+                }
+            }
+        }
+        let mut changes = Vec::new();
+        for (iface, before) in before {
+            let after = match self.prefixes.get(&iface) {
+                Some(prefixes) if !prefixes.is_empty() => current(prefixes),
+                _ => {
+                    self.prefixes.remove(&iface);
+                    BTreeSet::new()
+                }
+            };
+            let mut added: Vec<_> = after.difference(&before).copied().map(Some).collect();
+            let (if_index, if_name) = iface;
+            let change = |old, new| PrefixEvent {
+                if_index,
+                if_name: if_name.clone(),
+                old,
+                new,
+            };
+            for old in before.difference(&after).copied() {
+                let new = added.iter_mut().find(|new| {
+                    new.is_some_and(|new| {
+                        new.addr().is_ipv4() == old.addr().is_ipv4()
+                            && new.prefix_len() == old.prefix_len()
+                    })
+                });
+                changes.push(change(Some(old), new.and_then(Option::take)));
+            }
+            changes.extend(
+                added
+                    .into_iter()
+                    .flatten()
+                    .map(|new| change(None, Some(new))),
+            );
+        }
+        changes
+    }
+}
+
+/// The prefixes with an address that is not deprecated.
+fn current(prefixes: &Prefixes) -> BTreeSet<IpNet> {
+    prefixes
+        .iter()
+        .filter(|(_, addrs)| addrs.values().any(|deprecated| !deprecated))
+        .map(|(prefix, _)| *prefix)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrefixTracker;
+    use crate::{IfAddress, IfAddressEvent, IfAddressFlags, PrefixEvent};
+
+    fn event(up: bool, net: &str, if_index: u32) -> IfAddressEvent {
+        let addr = IfAddress::test(net, if_index, &format!("eth{}", if_index));
+        match up {
+            true => IfAddressEvent::Up(addr),
+            false => IfAddressEvent::Down(addr),
+        }
+    }
+
+    fn deprecate(net: &str, if_index: u32) -> IfAddressEvent {
+        let old = event(true, net, if_index).address().clone();
+        let flags = IfAddressFlags {
+            deprecated: true,
+            ..Default::default()
+        };
+        let new = IfAddress {
+            flags: Some(flags),
+            ..old.clone()
+        };
+        IfAddressEvent::Changed { old, new }
+    }
+
+    fn prefix_event(if_index: u32, old: Option<&str>, new: Option<&str>) -> PrefixEvent {
+        PrefixEvent {
+            if_index: Some(if_index),
+            if_name: format!("eth{}", if_index),
+            old: old.map(|net| net.parse().unwrap()),
+            new: new.map(|net| net.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_prefix_tracker() {
+        let mut tracker = PrefixTracker::new();
+        let initial = [
+            event(true, "2001:db8:1::2/64", 1),
+            event(true, "2001:db8:1::3/64", 1),
+            event(true, "fe80::1/64", 1),
+            event(true, "192.168.1.2/24", 2),
+        ];
+        assert_eq!(
+            tracker.update(&initial),
+            [
+                prefix_event(1, None, Some("2001:db8:1::/64")),
+                prefix_event(1, None, Some("fe80::/64")),
+                prefix_event(2, None, Some("192.168.1.0/24")),
+            ]
+        );
+
+        // Removing one of several addresses does not change the prefixes.
+        assert_eq!(tracker.update(&[event(false, "2001:db8:1::3/64", 1)]), []);
+
+        // The delegated prefix is replaced, with the new address added first.
+        let renumber = [
+            event(true, "2001:db8:2::2/64", 1),
+            event(false, "2001:db8:1::2/64", 1),
+        ];
+        assert_eq!(
+            tracker.update(&renumber),
+            [prefix_event(
+                1,
+                Some("2001:db8:1::/64"),
+                Some("2001:db8:2::/64")
+            )]
+        );
+
+        // The subnet changes, and an unrelated IPv6 prefix is added.
+        let subnet = [
+            event(false, "192.168.1.2/24", 2),
+            event(true, "2001:db8:3::2/64", 2),
+            event(true, "10.0.0.2/24", 2),
+        ];
+        assert_eq!(
+            tracker.update(&subnet),
+            [
+                prefix_event(2, Some("192.168.1.0/24"), Some("10.0.0.0/24")),
+                prefix_event(2, None, Some("2001:db8:3::/64")),
+            ]
+        );
+
+        // Prefixes of different lengths are not paired up.
+        let resize = [
+            event(false, "10.0.0.2/24", 2),
+            event(true, "10.0.0.2/16", 2),
+        ];
+        assert_eq!(
+            tracker.update(&resize),
+            [
+                prefix_event(2, Some("10.0.0.0/24"), None),
+                prefix_event(2, None, Some("10.0.0.0/16")),
+            ]
+        );
+
+        // Changes in separate batches are not paired up.
+        assert_eq!(
+            tracker.update(&[event(false, "2001:db8:2::2/64", 1)]),
+            [prefix_event(1, Some("2001:db8:2::/64"), None)]
+        );
+        assert_eq!(
+            tracker.update(&[event(true, "2001:db8:4::2/64", 1)]),
+            [prefix_event(1, None, Some("2001:db8:4::/64"))]
+        );
+
+        let prefixes: Vec<_> = tracker
+            .prefixes()
+            .map(|(_, name, prefix)| format!("{} {}", name, prefix))
+            .collect();
+        assert_eq!(
+            prefixes,
+            [
+                "eth1 2001:db8:4::/64",
+                "eth1 fe80::/64",
+                "eth2 10.0.0.0/16",
+                "eth2 2001:db8:3::/64",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prefix_deprecation() {
+        let mut tracker = PrefixTracker::new();
+        tracker.update(&[event(true, "2001:db8:1::2/64", 1)]);
+
+        // The new prefix is announced and the old one is deprecated.
+        let renumber = [
+            event(true, "2001:db8:2::2/64", 1),
+            deprecate("2001:db8:1::2/64", 1),
+        ];
+        assert_eq!(
+            tracker.update(&renumber),
+            [prefix_event(
+                1,
+                Some("2001:db8:1::/64"),
+                Some("2001:db8:2::/64")
+            )]
+        );
+        let prefixes: Vec<_> = tracker.prefixes().map(|(_, _, prefix)| prefix).collect();
+        assert_eq!(prefixes, ["2001:db8:2::/64".parse().unwrap()]);
+
+        // The old address is removed once its valid lifetime expires.
+        assert_eq!(tracker.update(&[event(false, "2001:db8:1::2/64", 1)]), []);
+
+        // A prefix that is deprecated without a replacement.
+        assert_eq!(
+            tracker.update(&[deprecate("2001:db8:2::2/64", 1)]),
+            [prefix_event(1, Some("2001:db8:2::/64"), None)]
+        );
+        assert_eq!(tracker.update(&[event(false, "2001:db8:2::2/64", 1)]), []);
+        assert_eq!(tracker.prefixes().count(), 0);
+    }
+}
@@ -0,0 +1,96 @@
+//! Tracking of the address used to reach the internet on platforms without
+//! route lookups.
+use crate::ifaddrs::IfWatcher;
+use crate::{IfAddress, IpFamily, PrimaryEvent, PROBE_V4, PROBE_V6};
+use futures::ready;
+use futures::stream::{FusedStream, Stream};
+use std::collections::VecDeque;
+use std::io::Result;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Watches the preferred source address for reaching the internet, per
+/// address family.
+///
+/// The source address is determined by connecting a UDP socket to an address
+/// outside of the local networks, which does not send any packets, whenever
+/// an address changes. Route changes that do not coincide with an address
+/// change are not noticed on this platform. An event is emitted for each
+/// family whose source address changed, starting with the initial ones.
+#[derive(Debug)]
+pub struct PrimaryWatcher {
+    watcher: IfWatcher,
+    synced: bool,
+    primary: [Option<IfAddress>; 2],
+    queue: VecDeque<PrimaryEvent>,
+}
+
+impl PrimaryWatcher {
+    /// Create a watcher.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            watcher: IfWatcher::new()?,
+            synced: false,
+            primary: Default::default(),
+            queue: Default::default(),
+        })
+    }
+
+    /// The current primary address of `family`.
+    pub fn primary(&self, family: IpFamily) -> Option<&IfAddress> {
+        self.primary[family as usize].as_ref()
+    }
+
+    fn update(&mut self) {
+        for family in [IpFamily::V4, IpFamily::V6] {
+            let primary = &mut self.primary[family as usize];
+            let addrs = self.watcher.addresses();
+            if let Some(event) = crate::update_primary(primary, family, probe_source(family), addrs)
+            {
+                self.queue.push_back(event);
+            }
+        }
+    }
+
+    /// Poll for a change of the primary address.
+    pub fn poll_primary_event(&mut self, cx: &mut Context) -> Poll<Result<PrimaryEvent>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+            if !self.synced {
+                ready!(self.watcher.poll_synced(cx))?;
+                self.synced = true;
+            } else {
+                ready!(self.watcher.poll_address_event(cx))?;
+            }
+            self.update();
+        }
+    }
+}
+
+/// Returns the source address the platform selects for sending to the probe
+/// address of `family`, or `None` if it is not reachable.
+pub(crate) fn probe_source(family: IpFamily) -> Option<IpAddr> {
+    let (local, probe): (SocketAddr, SocketAddr) = match family {
+        IpFamily::V4 => ((Ipv4Addr::UNSPECIFIED, 0).into(), (PROBE_V4, 9).into()),
+        IpFamily::V6 => ((Ipv6Addr::UNSPECIFIED, 0).into(), (PROBE_V6, 9).into()),
+    };
+    let socket = UdpSocket::bind(local).ok()?;
+    socket.connect(probe).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+impl Stream for PrimaryWatcher {
+    type Item = Result<PrimaryEvent>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self).poll_primary_event(cx).map(Some)
+    }
+}
+
+impl FusedStream for PrimaryWatcher {
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,271 @@
+//! Python bindings built with PyO3.
+//!
+//! The extension module is built with `maturin build` using the settings in
+//! `pyproject.toml`.
+use crate::blocking::BlockingWatcher;
+use crate::{IfAddress, IfAddressEvent};
+use futures::executor::block_on;
+use pyo3::prelude::*;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Interval at which a blocked iteration checks for signals such as
+/// `KeyboardInterrupt`.
+const SIGNAL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A local address together with the interface it is assigned to.
+#[pyclass(name = "Address", module = "if_watch", frozen, eq, hash)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PyAddress(IfAddress);
+
+#[pymethods]
+impl PyAddress {
+    /// The network in CIDR notation, e.g. `"192.168.1.2/24"`.
+    #[getter]
+    fn net(&self) -> String {
+        self.0.net.to_string()
+    }
+
+    /// The address as `ipaddress.IPv4Address` or `ipaddress.IPv6Address`.
+    #[getter]
+    fn ip(&self) -> IpAddr {
+        self.0.net.addr()
+    }
+
+    /// Prefix length of the network.
+    #[getter]
+    fn prefix_len(&self) -> u8 {
+        self.0.net.prefix_len()
+    }
+
+    /// Index of the interface, if the platform reports one.
+    #[getter]
+    fn if_index(&self) -> Option<u32> {
+        self.0.if_index
+    }
+
+    /// Name of the interface.
+    #[getter]
+    fn if_name(&self) -> &str {
+        &self.0.if_name
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Address(net='{}', if_index={}, if_name='{}')",
+            self.0.net,
+            self.0
+                .if_index
+                .map_or_else(|| "None".into(), |index| index.to_string()),
+            self.0.if_name,
+        )
+    }
+}
+
+/// An address that has been added, deleted or changed.
+#[pyclass(name = "AddressEvent", module = "if_watch", frozen, eq, hash)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PyAddressEvent(IfAddressEvent);
+
+#[pymethods]
+impl PyAddressEvent {
+    /// `"up"` if the address has been added, `"down"` if it has been deleted
+    /// and `"changed"` if its flags have changed.
+    #[getter]
+    fn kind(&self) -> &'static str {
+        match self.0 {
+            IfAddressEvent::Up(_) => "up",
+            IfAddressEvent::Down(_) => "down",
+            IfAddressEvent::Changed { .. } => "changed",
+        }
+    }
+
+    /// The address that has been added, deleted or changed.
+    #[getter]
+    fn address(&self) -> PyAddress {
+        PyAddress(self.0.address().clone())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "AddressEvent(kind='{}', address={})",
+            self.kind(),
+            self.address().__repr__()
+        )
+    }
+}
+
+/// Watches for address changes.
+///
+/// Iterating, synchronously or with `async for`, yields an `AddressEvent` for
+/// every current address followed by events for addresses being added or
+/// deleted.
+#[pyclass(name = "IfWatcher", module = "if_watch", frozen, weakref)]
+struct PyIfWatcher(BlockingWatcher);
+
+impl Drop for PyIfWatcher {
+    fn drop(&mut self) {
+        // The watcher thread takes the GIL to wake pending `__anext__` calls.
+        Python::with_gil(|py| py.allow_threads(|| self.0.stop()));
+    }
+}
+
+#[pymethods]
+impl PyIfWatcher {
+    #[new]
+    fn new(py: Python<'_>) -> PyResult<Self> {
+        Ok(Self(py.allow_threads(BlockingWatcher::new)?))
+    }
+
+    /// Returns the current addresses.
+    fn addresses(&self) -> Vec<PyAddress> {
+        self.0.addresses().into_iter().map(PyAddress).collect()
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<PyAddressEvent> {
+        loop {
+            if let Some(event) = py.allow_threads(|| self.0.next(Some(SIGNAL_INTERVAL)))? {
+                return Ok(PyAddressEvent(event));
+            }
+            py.check_signals()?;
+        }
+    }
+
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Returns a future completed on the running event loop once an event is
+    /// queued. Cancelling it leaves the event queued for the next call.
+    fn __anext__<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let py = slf.py();
+        let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+        let future = event_loop.call_method0("create_future")?;
+        let waiter = Waiter {
+            watcher: py.import("weakref")?.call_method1("ref", (slf,))?.unbind(),
+            event_loop: event_loop.unbind(),
+            future: future.clone().unbind(),
+        };
+        wait(slf.get(), Py::new(py, waiter)?);
+        Ok(future)
+    }
+}
+
+/// Completes the future returned by `IfWatcher.__anext__`, called on the
+/// event loop once the watcher thread has queued an event.
+#[pyclass(frozen)]
+struct Waiter {
+    /// Weak reference to the watcher, so that a pending future does not keep
+    /// it alive.
+    watcher: PyObject,
+    event_loop: PyObject,
+    future: PyObject,
+}
+
+#[pymethods]
+impl Waiter {
+    fn __call__(slf: &Bound<'_, Self>) -> PyResult<()> {
+        let py = slf.py();
+        let this = slf.get();
+        let future = this.future.bind(py);
+        // The future has been cancelled.
+        if future.call_method0("done")?.is_truthy()? {
+            return Ok(());
+        }
+        let watcher = this.watcher.call0(py)?;
+        let Ok(watcher) = watcher.downcast_bound::<PyIfWatcher>(py) else {
+            return Ok(());
+        };
+        match watcher.get().0.next(Some(Duration::ZERO)) {
+            Ok(Some(event)) => {
+                future.call_method1("set_result", (PyAddressEvent(event),))?;
+            }
+            // Another iteration took the event.
+            Ok(None) => wait(watcher.get(), slf.clone().unbind()),
+            Err(err) => {
+                future.call_method1("set_exception", (PyErr::from(err).into_value(py),))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Schedules `waiter` on its event loop once `watcher` has queued an event.
+fn wait(watcher: &PyIfWatcher, waiter: Py<Waiter>) {
+    watcher.0.wake(Box::new(move || {
+        Python::with_gil(|py| {
+            let event_loop = waiter.get().event_loop.clone_ref(py);
+            // Fails if the event loop has been closed in the meantime.
+            let _ = event_loop.call_method1(py, "call_soon_threadsafe", (waiter,));
+        })
+    }));
+}
+
+/// Returns the current addresses, matching the initial events of an
+/// `IfWatcher`.
+#[pyfunction]
+fn current_addresses(py: Python<'_>) -> PyResult<Vec<PyAddress>> {
+    let addrs = py.allow_threads(|| block_on(crate::smol::current_addresses()))?;
+    Ok(addrs.into_iter().map(PyAddress).collect())
+}
+
+/// Cross platform asynchronous network watcher.
+#[pymodule]
+fn if_watch(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyAddress>()?;
+    m.add_class::<PyAddressEvent>()?;
+    m.add_class::<PyIfWatcher>()?;
+    m.add_function(wrap_pyfunction!(current_addresses, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PyIfWatcher;
+    use pyo3::ffi::c_str;
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_anext_cancel() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("IfWatcher", py.get_type::<PyIfWatcher>())
+                .unwrap();
+            py.run(
+                c_str!(
+                    r#"
+import asyncio, threading
+
+async def main():
+    watcher = IfWatcher()
+    addrs = watcher.addresses()
+    for _ in addrs:
+        event = await watcher.__anext__()
+        assert event.kind == "up" and event.address in addrs
+    threads = threading.active_count()
+    for _ in range(3):
+        try:
+            await asyncio.wait_for(watcher.__anext__(), 0.05)
+            raise AssertionError("unexpected event")
+        except asyncio.TimeoutError:
+            pass
+    # No thread is left waiting for the cancelled calls.
+    assert threading.active_count() == threads
+
+asyncio.run(main())
+"#
+                ),
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
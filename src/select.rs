@@ -0,0 +1,279 @@
+//! Source address selection as specified in RFC 6724, section 5.
+use crate::IfAddress;
+use std::cmp::Ordering;
+use std::net::{IpAddr, Ipv6Addr};
+
+/// Scope of interface-local and link-local addresses.
+const SCOPE_LINK_LOCAL: u8 = 0x2;
+/// Scope of the deprecated site-local addresses.
+const SCOPE_SITE_LOCAL: u8 = 0x5;
+/// Scope of global addresses.
+const SCOPE_GLOBAL: u8 = 0xe;
+
+/// Prefixes and labels of the default policy table, RFC 6724 section 2.1.
+/// Precedences are only relevant for destination address selection.
+const POLICY_TABLE: &[(Ipv6Addr, u8, u8)] = &[
+    (Ipv6Addr::LOCALHOST, 128, 0),
+    (Ipv6Addr::UNSPECIFIED, 0, 1),
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0, 0), 96, 4),
+    (Ipv6Addr::new(0x2002, 0, 0, 0, 0, 0, 0, 0), 16, 2),
+    (Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0), 32, 5),
+    (Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7, 13),
+    (Ipv6Addr::UNSPECIFIED, 96, 3),
+    (Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 0), 10, 11),
+    (Ipv6Addr::new(0x3ffe, 0, 0, 0, 0, 0, 0, 0), 16, 12),
+];
+
+/// Selects the best source address among `candidates` for sending to `dst`,
+/// following the rules of RFC 6724 section 5.
+///
+/// Only candidates of the same address family as `dst` are considered, and
+/// tentative addresses are skipped. Rules that depend on information not
+/// tracked by the watcher, namely home addresses (rule 4), the outgoing
+/// interface (rule 5) and next-hop support (rule 5.5), are not applied.
+/// Deprecated and temporary addresses are only recognized on Linux. Remaining
+/// ties are broken by preferring the smallest address.
+pub fn select_source<'a, I>(dst: IpAddr, candidates: I) -> Option<&'a IfAddress>
+where
+    I: IntoIterator<Item = &'a IfAddress>,
+{
+    candidates
+        .into_iter()
+        .filter(|addr| addr.net.addr().is_ipv4() == dst.is_ipv4())
+        .filter(|addr| !addr.flags.is_some_and(|flags| flags.tentative))
+        .max_by(|a, b| compare(dst, a, b).then_with(|| b.cmp(a)))
+}
+
+/// Compares two candidate source addresses for `dst`. `Greater` means `a` is
+/// preferred.
+fn compare(dst: IpAddr, a: &IfAddress, b: &IfAddress) -> Ordering {
+    let (sa, sb) = (a.net.addr(), b.net.addr());
+    // Rule 1: prefer same address.
+    if sa == dst || sb == dst {
+        return (sa == dst).cmp(&(sb == dst));
+    }
+    // Rule 2: prefer appropriate scope.
+    let (scope_a, scope_b, scope_d) = (scope(sa), scope(sb), scope(dst));
+    if scope_a != scope_b {
+        let smaller_is_better = scope_a.min(scope_b) >= scope_d;
+        return match (scope_a < scope_b, smaller_is_better) {
+            (true, true) | (false, false) => Ordering::Greater,
+            (true, false) | (false, true) => Ordering::Less,
+        };
+    }
+    // Rule 3: avoid deprecated addresses.
+    let deprecated = |addr: &IfAddress| addr.flags.is_some_and(|flags| flags.deprecated);
+    let order = deprecated(b).cmp(&deprecated(a));
+    if order != Ordering::Equal {
+        return order;
+    }
+    // Rule 6: prefer matching label.
+    let label_d = label(dst);
+    let order = (label(sa) == label_d).cmp(&(label(sb) == label_d));
+    if order != Ordering::Equal {
+        return order;
+    }
+    // Rule 7: prefer temporary addresses.
+    let temporary = |addr: &IfAddress| addr.flags.is_some_and(|flags| flags.temporary);
+    let order = temporary(a).cmp(&temporary(b));
+    if order != Ordering::Equal {
+        return order;
+    }
+    // Rule 8: use longest matching prefix, up to the prefix of the source.
+    let common_prefix =
+        |addr: &IfAddress| common_prefix_len(addr.net.addr(), dst).min(addr.net.prefix_len());
+    common_prefix(a).cmp(&common_prefix(b))
+}
+
+/// Scope of an address, RFC 6724 section 3.
+fn scope(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(ip) if ip.is_loopback() || ip.is_link_local() => SCOPE_LINK_LOCAL,
+        IpAddr::V4(_) => SCOPE_GLOBAL,
+        IpAddr::V6(ip) if ip.is_multicast() => ip.octets()[1] & 0xf,
+        IpAddr::V6(ip) if ip.is_loopback() => SCOPE_LINK_LOCAL,
+        IpAddr::V6(ip) if crate::is_link_local_v6(&ip) => SCOPE_LINK_LOCAL,
+        IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfec0 => SCOPE_SITE_LOCAL,
+        IpAddr::V6(_) => SCOPE_GLOBAL,
+    }
+}
+
+/// Label of the longest matching prefix of the policy table. IPv4 addresses
+/// are looked up as IPv4-mapped IPv6 addresses.
+fn label(addr: IpAddr) -> u8 {
+    let ip = match addr {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    };
+    POLICY_TABLE
+        .iter()
+        .filter(|(prefix, len, _)| common_prefix_len((*prefix).into(), ip.into()) >= *len)
+        .max_by_key(|(_, len, _)| *len)
+        .map_or(1, |(_, _, label)| *label)
+}
+
+/// Number of leading bits `a` and `b` have in common.
+fn common_prefix_len(a: IpAddr, b: IpAddr) -> u8 {
+    match (a, b) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => (u32::from(a) ^ u32::from(b)).leading_zeros() as u8,
+        (IpAddr::V6(a), IpAddr::V6(b)) => (u128::from(a) ^ u128::from(b)).leading_zeros() as u8,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::select_source;
+    use crate::{IfAddress, IfAddressFlags};
+    use std::net::IpAddr;
+
+    /// Parses a candidate like `2001:db8::1/64 deprecated temporary`.
+    fn candidate(spec: &str) -> IfAddress {
+        let mut words = spec.split_whitespace();
+        let net = words.next().unwrap();
+        let mut flags = IfAddressFlags::default();
+        for word in words {
+            match word {
+                "deprecated" => flags.deprecated = true,
+                "temporary" => flags.temporary = true,
+                "tentative" => flags.tentative = true,
+                _ => panic!("unknown flag {}", word),
+            }
+        }
+        IfAddress {
+            flags: Some(flags),
+            ..IfAddress::test(net, 2, "eth0")
+        }
+    }
+
+    #[test]
+    fn test_select_source() {
+        // Destination, candidates and the expected source. The IPv6 cases up
+        // to the rule 7 example are from RFC 6724 section 10.1.
+        let cases: &[(&str, &[&str], Option<&str>)] = &[
+            // Rule 2: prefer appropriate scope.
+            (
+                "2001:db8:1::1",
+                &["2001:db8:3::1/64", "fe80::1/64"],
+                Some("2001:db8:3::1"),
+            ),
+            (
+                "ff05::1",
+                &["2001:db8:3::1/64", "fe80::1/64"],
+                Some("2001:db8:3::1"),
+            ),
+            // Rule 1: prefer same address.
+            (
+                "2001:db8:1::1",
+                &["2001:db8:1::1/64 deprecated", "2001:db8:2::1/64"],
+                Some("2001:db8:1::1"),
+            ),
+            // Rule 2 before rule 3.
+            (
+                "fe80::1",
+                &["fe80::2/64 deprecated", "2001:db8:1::1/64"],
+                Some("fe80::2"),
+            ),
+            // Rule 8: use longest matching prefix.
+            (
+                "2001:db8:1::1",
+                &["2001:db8:1::2/64", "2001:db8:3::2/64"],
+                Some("2001:db8:1::2"),
+            ),
+            // Rule 6: prefer matching label.
+            (
+                "2002:c633:6401::1",
+                &[
+                    "2002:c633:6401::d5e3:7953:13eb:22e8/64 temporary",
+                    "2001:db8:1::2/64",
+                ],
+                Some("2002:c633:6401::d5e3:7953:13eb:22e8"),
+            ),
+            // Rule 7: prefer temporary addresses.
+            (
+                "2001:db8:1::d5e3:0:0:1",
+                &[
+                    "2001:db8:1::2/64",
+                    "2001:db8:1::d5e3:7953:13eb:22e8/64 temporary",
+                ],
+                Some("2001:db8:1::d5e3:7953:13eb:22e8"),
+            ),
+            // Rule 3: avoid deprecated addresses.
+            (
+                "2001:db8:5::1",
+                &["2001:db8:1::1/64 deprecated", "2001:db8:2::1/64"],
+                Some("2001:db8:2::1"),
+            ),
+            // Rule 2: larger scope if the destination is not reachable with
+            // the smaller one.
+            ("ff0e::1", &["fe80::1/64", "fd00::1/64"], Some("fd00::1")),
+            ("ff02::1", &["fe80::1/64", "fd00::1/64"], Some("fe80::1")),
+            // Rule 6: unique local addresses for unique local destinations.
+            (
+                "fd12:3456::1",
+                &["2001:db8::1/64", "fd00::1/64"],
+                Some("fd00::1"),
+            ),
+            // Rule 8 only considers the prefix of the source.
+            (
+                "2001:db8::ffff:0:0:1",
+                &["2001:db8::ffff:0:0:2/48", "2001:db8::1/64"],
+                Some("2001:db8::1"),
+            ),
+            // Loopback has link-local scope.
+            ("::1", &["::1/128", "2001:db8::1/64"], Some("::1")),
+            ("2001:db8::2", &["::1/128", "fe80::1/64"], Some("fe80::1")),
+            // IPv4 scopes: loopback and link-local versus global.
+            (
+                "8.8.8.8",
+                &["127.0.0.1/8", "192.168.1.2/24"],
+                Some("192.168.1.2"),
+            ),
+            (
+                "169.254.1.1",
+                &["192.168.1.2/24", "169.254.2.2/16"],
+                Some("169.254.2.2"),
+            ),
+            (
+                "127.0.0.1",
+                &["127.0.0.1/8", "10.0.0.1/8"],
+                Some("127.0.0.1"),
+            ),
+            // IPv4 longest matching prefix.
+            (
+                "192.168.1.1",
+                &["10.0.0.2/8", "192.168.1.2/24"],
+                Some("192.168.1.2"),
+            ),
+            (
+                "10.1.2.3",
+                &["192.168.1.2/24", "10.1.0.5/16"],
+                Some("10.1.0.5"),
+            ),
+            // Ties are broken by the smallest address.
+            ("8.8.8.8", &["10.0.0.2/8", "10.0.0.1/8"], Some("10.0.0.1")),
+            // Only addresses of the same family are candidates.
+            ("2001:db8::1", &["192.168.1.2/24"], None),
+            ("192.168.1.1", &["fe80::1/64"], None),
+            // Tentative addresses are not candidates.
+            ("2001:db8::2", &["2001:db8::1/64 tentative"], None),
+            (
+                "2001:db8::2",
+                &["2001:db8::1/64 tentative", "fe80::1/64"],
+                Some("fe80::1"),
+            ),
+            ("2001:db8::1", &[], None),
+        ];
+        for (dst, candidates, expected) in cases {
+            let dst: IpAddr = dst.parse().unwrap();
+            let candidates: Vec<_> = candidates.iter().map(|spec| candidate(spec)).collect();
+            let selected = select_source(dst, &candidates).map(|addr| addr.net.addr());
+            let expected = expected.map(|ip| ip.parse::<IpAddr>().unwrap());
+            assert_eq!(
+                selected, expected,
+                "destination {} from {:?}",
+                dst, candidates
+            );
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Metrics of the backends, recorded with the `metrics` facade.
+//!
+//! Every metric has a `backend` label naming the platform backend.
+use crate::{IfAddressEvent, StampedEvent};
+use metrics::{counter, gauge};
+use std::io::Error;
+
+/// Counts an address event returned by a watcher.
+pub(crate) fn event(backend: &'static str, stamped: &StampedEvent) {
+    let kind = match stamped.event {
+        IfAddressEvent::Up(_) => "up",
+        IfAddressEvent::Down(_) => "down",
+        IfAddressEvent::Changed { .. } => "changed",
+    };
+    counter!("if_watch_events_total", "backend" => backend, "kind" => kind).increment(1);
+}
+
+/// Records the number of queued address events.
+pub(crate) fn queue(backend: &'static str, len: usize) {
+    gauge!("if_watch_queue_size", "backend" => backend).set(len as f64);
+}
+
+/// Counts an error of a backend by its kind.
+pub(crate) fn error(backend: &'static str, err: &Error) {
+    let kind = format!("{:?}", err.kind());
+    counter!("if_watch_errors_total", "backend" => backend, "kind" => kind).increment(1);
+}
+
+/// Records the duration of comparing the current addresses to the known
+/// ones, or of dumping them again after an overrun on Linux.
+pub(crate) fn resync(backend: &'static str, duration: std::time::Duration) {
+    metrics::histogram!("if_watch_resync_duration_seconds", "backend" => backend).record(duration);
+}
+
+/// Counts an error receiving from a netlink socket, which is also counted as
+/// an overrun if notifications were lost because the receive buffer of the
+/// socket overflowed.
+#[cfg(target_os = "linux")]
+pub(crate) fn recv_error(backend: &'static str, err: &Error) {
+    if err.raw_os_error() == Some(libc::ENOBUFS) {
+        counter!("if_watch_netlink_overruns_total", "backend" => backend).increment(1);
+    }
+    error(backend, err);
+}
@@ -0,0 +1,52 @@
+//! Structured `tracing` events of the backends.
+//!
+//! Every event has a `backend` field naming the platform backend.
+use crate::{IfAddressEvent, StampedEvent};
+
+/// Emits an event for a watcher that has been created.
+pub(crate) fn setup(backend: &'static str, netns: Option<u64>) {
+    tracing::debug!(backend, netns, "watcher created");
+}
+
+/// Emits an event for a notification received from the operating system.
+#[cfg(any(
+    target_os = "ios",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "windows",
+))]
+pub(crate) fn notification(backend: &'static str, kind: &str, kernel_seq: Option<u32>) {
+    tracing::trace!(backend, kind, kernel_seq, "notification received");
+}
+
+/// Emits an event for the initial addresses having been loaded.
+#[cfg(target_os = "linux")]
+pub(crate) fn synced(backend: &'static str, addresses: usize) {
+    tracing::debug!(backend, addresses, "initial addresses loaded");
+}
+
+/// Emits an event with the result of comparing the current addresses to the
+/// known ones, given the number of addresses added and removed.
+pub(crate) fn resync(backend: &'static str, up: usize, down: usize) {
+    tracing::debug!(backend, up, down, "addresses resynced");
+}
+
+/// Emits an event for an address event returned by a watcher.
+pub(crate) fn event(backend: &'static str, stamped: &StampedEvent) {
+    let kind = match &stamped.event {
+        IfAddressEvent::Up(_) => "up",
+        IfAddressEvent::Down(_) => "down",
+        IfAddressEvent::Changed { .. } => "changed",
+    };
+    let addr = stamped.event.address();
+    tracing::debug!(
+        backend,
+        kind,
+        address = %addr.net,
+        interface = %addr.if_name,
+        if_index = addr.if_index,
+        seq = stamped.seq,
+        kernel_seq = stamped.kernel_seq,
+        "address event",
+    );
+}
@@ -1,12 +1,6 @@
-use crate::{IfEvent, IpNet, Ipv4Net, Ipv6Net};
-use fnv::FnvHashSet;
-use futures::stream::{FusedStream, Stream};
 use futures::task::AtomicWaker;
-use if_addrs::IfAddr;
-use std::collections::VecDeque;
 use std::ffi::c_void;
-use std::io::{Error, ErrorKind, Result};
-use std::pin::Pin;
+use std::io::{Error, Result};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -16,14 +10,22 @@ use windows::Win32::NetworkManagement::IpHelper::{
 };
 use windows::Win32::Networking::WinSock::AF_UNSPEC;
 
+/// Name of the backend in `tracing` events and metrics.
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+pub(crate) const BACKEND: &str = "windows";
+
 #[cfg(feature = "tokio")]
 pub mod tokio {
     //! An interface watcher.
     //! **On Windows there is no difference between `tokio` and `smol` features,**
     //! **this was done to maintain the api compatible with other platforms**.
 
+    pub use crate::connectivity::ConnectivityWatcher;
+    pub use crate::ifaddrs::current_addresses;
+    pub use crate::primary::PrimaryWatcher;
+
     /// Watches for interface changes.
-    pub type IfWatcher = super::IfWatcher;
+    pub type IfWatcher = crate::ifaddrs::IfWatcher;
 }
 
 #[cfg(feature = "smol")]
@@ -32,107 +34,47 @@ pub mod smol {
     //! **On Windows there is no difference between `tokio` and `smol` features,**
     //! **this was done to maintain the api compatible with other platforms**.
 
+    pub use crate::connectivity::ConnectivityWatcher;
+    pub use crate::ifaddrs::current_addresses;
+    pub use crate::primary::PrimaryWatcher;
+
     /// Watches for interface changes.
-    pub type IfWatcher = super::IfWatcher;
+    pub type IfWatcher = crate::ifaddrs::IfWatcher;
 }
 
-/// An address set/watcher
+/// Reports changes of the IP interfaces, which are signalled by
+/// `NotifyIpInterfaceChange`.
 #[derive(Debug)]
-pub struct IfWatcher {
-    addrs: FnvHashSet<IpNet>,
-    queue: VecDeque<IfEvent>,
+pub(crate) struct Notifier {
     #[allow(unused)]
     notif: IpChangeNotification,
     waker: Arc<AtomicWaker>,
     resync: Arc<AtomicBool>,
 }
 
-impl IfWatcher {
-    /// Create a watcher.
-    pub fn new() -> Result<Self> {
-        let resync = Arc::new(AtomicBool::new(true));
+impl Notifier {
+    pub(crate) fn new() -> Result<Self> {
+        let resync = Arc::new(AtomicBool::new(false));
         let waker = Arc::new(AtomicWaker::new());
         Ok(Self {
-            addrs: Default::default(),
-            queue: Default::default(),
             waker: waker.clone(),
             resync: resync.clone(),
             notif: IpChangeNotification::new(Box::new(move |_, _| {
+                #[cfg(feature = "tracing")]
+                crate::trace::notification(BACKEND, "ip_interface_change", None);
                 resync.store(true, Ordering::Relaxed);
                 waker.wake();
             }))?,
         })
     }
 
-    fn resync(&mut self) -> Result<()> {
-        let addrs = if_addrs::get_if_addrs()?;
-        for old_addr in self.addrs.clone() {
-            if addrs
-                .iter()
-                .find(|addr| addr.ip() == old_addr.addr())
-                .is_none()
-            {
-                self.addrs.remove(&old_addr);
-                self.queue.push_back(IfEvent::Down(old_addr));
-            }
-        }
-        for new_addr in addrs {
-            let ipnet = ifaddr_to_ipnet(new_addr.addr);
-            if self.addrs.insert(ipnet) {
-                self.queue.push_back(IfEvent::Up(ipnet));
-            }
-        }
-        Ok(())
-    }
-
-    /// Iterate over current networks.
-    pub fn iter(&self) -> impl Iterator<Item = &IpNet> {
-        self.addrs.iter()
-    }
-
-    /// Poll for an address change event.
-    pub fn poll_if_event(&mut self, cx: &mut Context) -> Poll<Result<IfEvent>> {
-        loop {
-            if let Some(event) = self.queue.pop_front() {
-                return Poll::Ready(Ok(event));
-            }
-            if !self.resync.swap(false, Ordering::Relaxed) {
-                self.waker.register(cx.waker());
-                return Poll::Pending;
-            }
-            if let Err(error) = self.resync() {
-                return Poll::Ready(Err(error));
-            }
-        }
-    }
-}
-
-impl Stream for IfWatcher {
-    type Item = Result<IfEvent>;
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::into_inner(self).poll_if_event(cx).map(Some)
-    }
-}
-
-impl FusedStream for IfWatcher {
-    fn is_terminated(&self) -> bool {
-        false
-    }
-}
-
-fn ifaddr_to_ipnet(addr: IfAddr) -> IpNet {
-    match addr {
-        IfAddr::V4(ip) => {
-            let prefix_len = (!u32::from_be_bytes(ip.netmask.octets())).leading_zeros();
-            IpNet::V4(
-                Ipv4Net::new(ip.ip, prefix_len as u8).expect("if_addrs returned a valid prefix"),
-            )
-        }
-        IfAddr::V6(ip) => {
-            let prefix_len = (!u128::from_be_bytes(ip.netmask.octets())).leading_zeros();
-            IpNet::V6(
-                Ipv6Net::new(ip.ip, prefix_len as u8).expect("if_addrs returned a valid prefix"),
-            )
+    pub(crate) fn poll_change(&mut self, cx: &mut Context) -> Poll<()> {
+        // Register first so that no notification is missed.
+        self.waker.register(cx.waker());
+        if self.resync.swap(false, Ordering::Relaxed) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
         }
     }
 }
@@ -171,7 +113,7 @@ impl IpChangeNotification {
                 BOOLEAN(0),
                 &mut handle as _,
             )
-            .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?;
+            .map_err(|err| Error::other(err.to_string()))?;
         }
         Ok(Self { callback, handle })
     }